@@ -0,0 +1,448 @@
+use crate::*;
+
+// Settling more tokens does not fit in the prepaid gas limit
+const MAX_BUNDLE_ITEMS: usize = 3;
+const MAX_BUNDLE_WEIGHT: u32 = 10_000;
+// Used by `offer_bundle` besides its items
+const GAS_FOR_OFFER_BUNDLE: Gas = 10_000_000_000_000;
+// Removal of one bundled sale in `offer_bundle`, 24 Tgas with 100k sales listed
+const GAS_FOR_BUNDLE_ITEM: Gas = 25_000_000_000_000;
+const GAS_FOR_RESOLVE_BUNDLE_PURCHASE: Gas = 10_000_000_000_000;
+// Handing one token to the buyer and removing its uses, or relisting it for the seller, in
+// `resolve_bundle_purchase`, 33 Tgas with 100k sales and uses listed
+const GAS_FOR_RESOLVE_BUNDLE_ITEM: Gas = 35_000_000_000_000;
+
+#[derive(BorshSerialize, BorshDeserialize, Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct BundleItem {
+    pub nft_contract_id: NFTContractId,
+    pub token_id: TokenId,
+    // Share of the bundle price used as `balance` for this token's payout
+    pub weight: u32,
+}
+
+#[derive(BorshSerialize, BorshDeserialize, Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct Bundle {
+    pub bundle_id: u64,
+    pub owner_id: AccountId,
+    pub items: Vec<BundleItem>,
+    pub price: SalePriceInYoctoNear,
+}
+
+#[ext_contract(ext_self)]
+pub trait BundleResolver {
+    fn resolve_bundle_purchase(
+        &mut self,
+        buyer_id: AccountId,
+        sales: Vec<Sale>,
+        shares: Vec<U128>,
+    ) -> U128;
+}
+
+/// Splits `price` between the bundle items by weight, rounding leftovers go to the last item.
+fn split_by_weight(price: Balance, items: &[BundleItem]) -> Vec<U128> {
+    let total_weight: u128 = items.iter().map(|item| u128::from(item.weight)).sum();
+    let mut left = price;
+    items
+        .iter()
+        .enumerate()
+        .map(|(index, item)| {
+            let share = if index + 1 == items.len() {
+                left
+            } else {
+                price * u128::from(item.weight) / total_weight
+            };
+            left -= share;
+            U128(share)
+        })
+        .collect()
+}
+
+#[near_bindgen]
+impl Contract {
+    /**
+    Groups tokens the caller already listed into one bundle sold at `price`.
+    Bundled tokens can no longer be bought on their own until the bundle is removed.
+     */
+    #[payable]
+    pub fn create_bundle(&mut self, items: Vec<BundleItem>, price: U128) -> u64 {
+        assert_one_yocto();
+        assert!(
+            items.len() >= 2 && items.len() <= MAX_BUNDLE_ITEMS,
+            "Bundle must have between 2 and {} tokens",
+            MAX_BUNDLE_ITEMS
+        );
+        let owner_id = env::predecessor_account_id();
+        let bundle_id = self.next_bundle_id;
+
+        for item in items.iter() {
+            assert!(
+                item.weight > 0 && item.weight <= MAX_BUNDLE_WEIGHT,
+                "Bundle weight must be between 1 and {}",
+                MAX_BUNDLE_WEIGHT
+            );
+            let contract_and_token_id =
+                format!("{}{}{}", item.nft_contract_id, DELIMETER, item.token_id);
+            let sale = self
+                .sales
+                .get(&contract_and_token_id)
+                .expect("Not found sale");
+            assert_eq!(sale.owner_id, owner_id, "Must be sale owner");
+            assert!(!sale.is_reserved(), "Reserved sales can not be bundled");
+            assert!(sale.drop_id.is_none(), "Drop sales can not be bundled");
+            assert!(
+                matches!(
+                    self.transfer_standards.get(&item.nft_contract_id),
+                    None | Some(TransferStandard::PayoutOnTransfer)
+                ),
+                "NFT contract {} does not support nft_transfer_payout",
+                item.nft_contract_id
+            );
+            assert!(
+                self.bundled_tokens
+                    .insert(&contract_and_token_id, &bundle_id)
+                    .is_none(),
+                "Token is already in a bundle"
+            );
+        }
+
+        self.next_bundle_id += 1;
+        self.bundles.insert(
+            &bundle_id,
+            &Bundle {
+                bundle_id,
+                owner_id,
+                items,
+                price,
+            },
+        );
+        bundle_id
+    }
+
+    #[payable]
+    pub fn remove_bundle(&mut self, bundle_id: u64) {
+        assert_one_yocto();
+        let bundle = self.internal_remove_bundle(bundle_id);
+        assert_eq!(
+            env::predecessor_account_id(),
+            bundle.owner_id,
+            "Must be owner id"
+        );
+    }
+
+    /**
+    Buys a bundle all or nothing. Every token is first moved to the market with
+    `nft_transfer_payout`, which also checks the market approval of the seller is still current,
+    and only handed to the buyer once all of them arrived.
+     */
+    #[payable]
+    pub fn offer_bundle(&mut self, bundle_id: u64) {
+        let deposit = env::attached_deposit();
+        let bundle = self.bundles.get(&bundle_id).expect("Not found bundle");
+        let buyer_id = env::predecessor_account_id();
+        self.assert_not_banned(&buyer_id);
+        assert_ne!(buyer_id, bundle.owner_id, "Can not bid on your own sale");
+        assert!(
+            deposit >= bundle.price.0 && deposit > 0,
+            "Attached deposit must be greater than or equal current price: {}",
+            bundle.price.0
+        );
+
+        let items_len = bundle.items.len() as u64;
+        let gas_for_resolve =
+            GAS_FOR_RESOLVE_BUNDLE_PURCHASE + items_len * GAS_FOR_RESOLVE_BUNDLE_ITEM;
+        let gas_needed = env::used_gas()
+            + GAS_FOR_OFFER_BUNDLE
+            + items_len * (GAS_FOR_BUNDLE_ITEM + GAS_FOR_NFT_TRANSFER + GAS_FOR_CALL_FEES)
+            + gas_for_resolve;
+        assert!(
+            env::prepaid_gas() >= gas_needed,
+            "Require at least {} prepaid gas",
+            gas_needed
+        );
+
+        let shares = split_by_weight(deposit, &bundle.items);
+        self.internal_remove_bundle(bundle_id);
+
+        let mut sales = vec![];
+        let mut transfers: Option<Promise> = None;
+        for (item, share) in bundle.items.iter().zip(shares.iter()) {
+            let sale =
+                self.internal_remove_sale(item.nft_contract_id.clone(), item.token_id.clone());
+            assert!(!sale.is_reserved(), "Reserved sales can not be bundled");
+            assert!(sale.drop_id.is_none(), "Drop sales can not be bundled");
+            let transfer = nft_contract::nft_transfer_payout(
+                env::current_account_id(),
+                sale.token_id.clone(),
+                sale.approval_id,
+                format!("Bundle {} from market contract", bundle_id),
+                *share,
+                self.payout_policy(&sale.nft_contract_id).max_len_payout,
+                &sale.nft_contract_id,
+                1,
+                GAS_FOR_NFT_TRANSFER,
+            );
+            transfers = Some(match transfers {
+                Some(transfers) => transfers.and(transfer),
+                None => transfer,
+            });
+            sales.push(sale);
+        }
+
+        let gas_left = env::prepaid_gas().saturating_sub(
+            env::used_gas()
+                + items_len * (GAS_FOR_NFT_TRANSFER + GAS_FOR_CALL_FEES)
+                + GAS_FOR_OFFER_BUNDLE,
+        );
+        assert!(
+            gas_left >= gas_for_resolve,
+            "Require at least {} prepaid gas",
+            gas_needed
+        );
+        transfers
+            .expect("Bundle has no tokens")
+            .then(ext_self::resolve_bundle_purchase(
+                buyer_id,
+                sales,
+                shares,
+                &env::current_account_id(),
+                NO_DEPOSIT,
+                gas_left,
+            ));
+    }
+
+    /**
+    When every token reached the market they go to the buyer, their uses are removed and each
+    share is paid out. Otherwise the bundle is rolled back: tokens that reached the market go
+    back to the seller and the buyer is refunded in full. Sales of tokens that did not move are
+    listed again, the others lost their approval and have to be approved again. A token whose
+    transfer out of the market fails stays in escrow until `recover_escrowed_token`.
+     */
+    #[private]
+    pub fn resolve_bundle_purchase(
+        &mut self,
+        buyer_id: AccountId,
+        sales: Vec<Sale>,
+        shares: Vec<U128>,
+    ) -> U128 {
+        let payouts: Vec<Option<Vec<u8>>> = (0..sales.len())
+            .map(|index| promise_result_at(index as u64))
+            .collect();
+
+        if payouts.iter().all(|payout| payout.is_some()) {
+            for ((sale, share), payout) in sales.into_iter().zip(shares).zip(payouts) {
+                self.internal_remove_uses(&sale.nft_contract_id, &sale.token_id);
+                nft_contract::nft_transfer(
+                    buyer_id.clone(),
+                    sale.token_id.clone(),
+                    None,
+                    Some("Payout from market contract".to_string()),
+                    &sale.nft_contract_id,
+                    1,
+                    GAS_FOR_NFT_TRANSFER,
+                );
+                let payout_policy = self.payout_policy(&sale.nft_contract_id);
+                match payout.and_then(|value| payout_policy.apply(&value, share, &sale.owner_id)) {
+                    Some(payout) => pay_out(payout),
+                    None => {
+                        Promise::new(sale.owner_id).transfer(share.0);
+                    }
+                }
+            }
+            return U128(0);
+        }
+
+        env::log("Bundle transfer failed, returning tokens and refunding buyer".as_bytes());
+        let refund: Balance = shares.iter().map(|share| share.0).sum();
+        for (sale, payout) in sales.into_iter().zip(payouts) {
+            let contract_and_token_id =
+                format!("{}{}{}", sale.nft_contract_id, DELIMETER, sale.token_id);
+            if payout.is_some() {
+                nft_contract::nft_transfer(
+                    sale.owner_id.clone(),
+                    sale.token_id.clone(),
+                    None,
+                    Some("Bundle rolled back by market contract".to_string()),
+                    &sale.nft_contract_id,
+                    1,
+                    GAS_FOR_NFT_TRANSFER,
+                );
+            } else if self.sales.get(&contract_and_token_id).is_none() {
+                self.internal_add_sale(&sale);
+            }
+        }
+
+        Promise::new(buyer_id).transfer(refund);
+        U128(refund)
+    }
+
+    /**
+    Hands out a token the market still holds because its transfer out of escrow failed, e.g. to
+    the buyer of a bundle or a swap party. Only the contract owner can call it.
+     */
+    #[payable]
+    pub fn recover_escrowed_token(
+        &mut self,
+        nft_contract_id: AccountId,
+        token_id: TokenId,
+        receiver_id: AccountId,
+    ) -> Promise {
+        assert_one_yocto();
+        self.assert_owner();
+        nft_contract::nft_transfer(
+            receiver_id,
+            token_id,
+            None,
+            Some("Recovered from market escrow".to_string()),
+            &nft_contract_id,
+            1,
+            GAS_FOR_NFT_TRANSFER,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use near_sdk::test_utils::{accounts, get_created_receipts, VMContextBuilder};
+    use near_sdk::{testing_env, MockedBlockchain, RuntimeFeesConfig, VMConfig};
+
+    const MAX_PREPAID_GAS: Gas = 300_000_000_000_000;
+    const PRICE: Balance = 1_000;
+
+    fn call(
+        predecessor_id: usize,
+        deposit: Balance,
+        prepaid_gas: Gas,
+        results: Vec<PromiseResult>,
+    ) {
+        testing_env!(
+            VMContextBuilder::new()
+                .current_account_id(accounts(0))
+                .predecessor_account_id(accounts(predecessor_id))
+                .attached_deposit(deposit)
+                .prepaid_gas(prepaid_gas)
+                .build(),
+            VMConfig::default(),
+            RuntimeFeesConfig::default(),
+            Default::default(),
+            results
+        );
+    }
+
+    /// Lists `token_ids` of `accounts(1)` on `accounts(2)` and bundles them.
+    fn bundle(contract: &mut Contract, token_ids: &[&str]) -> u64 {
+        for token_id in token_ids.iter() {
+            contract.internal_add_sale(&Sale {
+                owner_id: accounts(1).into(),
+                approval_id: 1,
+                nft_contract_id: accounts(2).into(),
+                token_id: token_id.to_string(),
+                sale_conditions: U128(PRICE),
+                reserved_for: vec![],
+                drop_id: None,
+                listed_at: U64(0),
+            });
+            contract.internal_add_uses(&Uses {
+                owner_id: accounts(1).into(),
+                nft_contract_id: accounts(2).into(),
+                token_id: token_id.to_string(),
+                use_conditions: U128(PRICE),
+                listed_at: U64(0),
+            });
+        }
+        call(1, 1, MAX_PREPAID_GAS, vec![]);
+        contract.create_bundle(
+            token_ids
+                .iter()
+                .map(|token_id| BundleItem {
+                    nft_contract_id: accounts(2).into(),
+                    token_id: token_id.to_string(),
+                    weight: 1,
+                })
+                .collect(),
+            U128(token_ids.len() as Balance * PRICE),
+        )
+    }
+
+    /// Receivers and gas of the calls of `method_name` the last call created.
+    fn calls(method_name: &str) -> Vec<(String, Gas)> {
+        get_created_receipts()
+            .iter()
+            .filter_map(|receipt| {
+                let receipt = near_sdk::serde_json::to_value(receipt).unwrap();
+                let receiver_id = receipt["receiver_id"].as_str()?.to_string();
+                let gas = receipt["actions"]
+                    .as_array()?
+                    .iter()
+                    .find(|action| action["FunctionCall"]["method_name"] == method_name)?
+                    ["FunctionCall"]["gas"]
+                    .as_u64()?;
+                Some((receiver_id, gas))
+            })
+            .collect()
+    }
+
+    #[test]
+    fn offer_bundle_fits_max_items() {
+        call(0, 0, MAX_PREPAID_GAS, vec![]);
+        let mut contract = Contract::new(accounts(0).into());
+        let token_ids = ["1", "2", "3"];
+        assert_eq!(token_ids.len(), MAX_BUNDLE_ITEMS);
+        let bundle_id = bundle(&mut contract, &token_ids);
+
+        let price = token_ids.len() as Balance * PRICE;
+        call(3, price, MAX_PREPAID_GAS, vec![]);
+        contract.offer_bundle(bundle_id);
+        assert_eq!(calls("nft_transfer_payout").len(), token_ids.len());
+        let resolve_gas = calls("resolve_bundle_purchase");
+        assert_eq!(resolve_gas.len(), 1);
+
+        let sales: Vec<Sale> = token_ids
+            .iter()
+            .map(|token_id| Sale {
+                owner_id: accounts(1).into(),
+                approval_id: 1,
+                nft_contract_id: accounts(2).into(),
+                token_id: token_id.to_string(),
+                sale_conditions: U128(PRICE),
+                reserved_for: vec![],
+                drop_id: None,
+                listed_at: U64(0),
+            })
+            .collect();
+        let payout = format!("{{\"payout\":{{\"{}\":\"{}\"}}}}", accounts(1), PRICE);
+        call(
+            0,
+            0,
+            resolve_gas[0].1,
+            token_ids
+                .iter()
+                .map(|_| PromiseResult::Successful(payout.clone().into_bytes()))
+                .collect(),
+        );
+        let refund = contract.resolve_bundle_purchase(
+            accounts(3).into(),
+            sales,
+            vec![U128(PRICE); token_ids.len()],
+        );
+        assert_eq!(refund.0, 0);
+        assert_eq!(contract.get_supply_uses().0, 0);
+        let handed_out = calls("nft_transfer");
+        assert_eq!(handed_out.len(), token_ids.len());
+        assert!(handed_out
+            .iter()
+            .all(|(receiver_id, gas)| receiver_id == &accounts(2).to_string()
+                && *gas == GAS_FOR_NFT_TRANSFER));
+    }
+
+    #[test]
+    #[should_panic(expected = "Only contract owner can call this method")]
+    fn recover_escrowed_token_requires_owner() {
+        call(0, 0, MAX_PREPAID_GAS, vec![]);
+        let mut contract = Contract::new(accounts(0).into());
+        call(3, 1, MAX_PREPAID_GAS, vec![]);
+        contract.recover_escrowed_token(accounts(2).into(), "1".to_string(), accounts(3).into());
+    }
+}
@@ -0,0 +1,31 @@
+use crate::*;
+
+#[near_bindgen]
+impl Contract {
+    pub fn get_supply_bundles(&self) -> U128 {
+        U128(self.bundles.len() as u128)
+    }
+
+    pub fn get_bundle(&self, bundle_id: u64) -> Option<Bundle> {
+        self.bundles.get(&bundle_id)
+    }
+
+    pub fn get_bundle_by_token(
+        &self,
+        nft_contract_id: NFTContractId,
+        token_id: TokenId,
+    ) -> Option<Bundle> {
+        self.bundled_tokens
+            .get(&format!("{}{}{}", nft_contract_id, DELIMETER, token_id))
+            .and_then(|bundle_id| self.bundles.get(&bundle_id))
+    }
+
+    /// Bundles in id order, pass `next_key` of the previous page as `from_key`.
+    pub fn get_bundles(&self, from_key: Option<U64>, limit: Option<U64>) -> Page<Bundle, U64> {
+        filtered_page(
+            ids_after(from_key, self.next_bundle_id),
+            limit,
+            |bundle_id| self.bundles.get(&bundle_id.0),
+        )
+    }
+}
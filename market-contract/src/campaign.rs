@@ -0,0 +1,221 @@
+use crate::*;
+
+const GAS_FOR_NFT_MINT: Gas = 30_000_000_000_000;
+const GAS_FOR_RESOLVE_MINT: Gas = 20_000_000_000_000;
+
+/// Metadata every token of a campaign is minted with, `title` gets the serial appended.
+#[derive(BorshSerialize, BorshDeserialize, Serialize, Deserialize, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct MetadataTemplate {
+    pub title: String,
+    pub description: Option<String>,
+    pub media: Option<String>,
+    pub reference: Option<String>,
+    pub extra: Option<String>,
+}
+
+/// NEP-177 token metadata passed to `nft_mint`.
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct TokenMetadata {
+    pub title: Option<String>,
+    pub description: Option<String>,
+    pub media: Option<String>,
+    pub copies: Option<u64>,
+    pub issued_at: Option<String>,
+    pub reference: Option<String>,
+    pub extra: Option<String>,
+}
+
+#[derive(BorshSerialize, BorshDeserialize, Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct Campaign {
+    pub campaign_id: u64,
+    pub creator_id: AccountId,
+    pub nft_contract_id: NFTContractId,
+    pub price: U128,
+    // Attached to `nft_mint` for the token storage, paid by the buyer on top of the price
+    pub mint_deposit: U128,
+    pub supply: u64,
+    pub minted: u64,
+    // Mints started but not resolved yet, they count against the supply
+    pub pending: u64,
+    pub next_serial: u64,
+    pub metadata: MetadataTemplate,
+}
+
+impl Campaign {
+    pub fn remaining(&self) -> u64 {
+        self.supply - self.minted - self.pending
+    }
+}
+
+#[ext_contract(ext_nft_mint)]
+pub trait NftMint {
+    fn nft_mint(&mut self, token_id: TokenId, metadata: TokenMetadata, receiver_id: AccountId);
+}
+
+#[ext_contract(ext_self)]
+pub trait CampaignResolver {
+    fn resolve_mint(
+        &mut self,
+        campaign_id: u64,
+        buyer_id: AccountId,
+        price: U128,
+        mint_deposit: U128,
+    ) -> bool;
+}
+
+#[near_bindgen]
+impl Contract {
+    /**
+    Opens a primary sale for an NFT contract the caller deployed through the factory.
+    Tokens are minted to buyers on purchase, so the child contract must let the market call `nft_mint`.
+    The attached deposit pays for the storage of the campaign, the rest is refunded.
+     */
+    #[payable]
+    pub fn create_campaign(
+        &mut self,
+        nft_contract_id: NFTContractId,
+        price: U128,
+        mint_deposit: U128,
+        supply: u64,
+        metadata: MetadataTemplate,
+    ) -> u64 {
+        let initial_storage_usage = env::storage_usage();
+        let creator_id = env::predecessor_account_id();
+        self.assert_not_banned(&creator_id);
+        let deployment = self
+            .internal_find_deployment(&creator_id, &nft_contract_id)
+            .unwrap_or_else(|| {
+                env::panic(format!("NFT contract was not deployed by {}", creator_id).as_bytes())
+            });
+        assert!(
+            deployment.status == DeploymentStatus::Active,
+            "Deployment was deleted"
+        );
+        assert!(supply > 0, "Supply must be greater than 0");
+
+        let campaign_id = self.next_campaign_id;
+        self.next_campaign_id += 1;
+        self.campaigns.insert(
+            &campaign_id,
+            &Campaign {
+                campaign_id,
+                creator_id: creator_id.clone(),
+                nft_contract_id,
+                price,
+                mint_deposit,
+                supply,
+                minted: 0,
+                pending: 0,
+                next_serial: 1,
+                metadata,
+            },
+        );
+
+        refund_deposit(env::storage_usage() - initial_storage_usage, creator_id);
+        campaign_id
+    }
+
+    /// Stops a campaign once no mint is in flight, tokens already minted are unaffected.
+    #[payable]
+    pub fn end_campaign(&mut self, campaign_id: u64) {
+        assert_one_yocto();
+        let campaign = self
+            .campaigns
+            .get(&campaign_id)
+            .expect("Not found campaign");
+        assert_eq!(
+            env::predecessor_account_id(),
+            campaign.creator_id,
+            "Must be campaign creator"
+        );
+        assert_eq!(campaign.pending, 0, "Campaign has mints in progress");
+        self.campaigns.remove(&campaign_id);
+    }
+
+    /// Buys the next token of a campaign, minted straight to the caller.
+    #[payable]
+    pub fn buy_primary(&mut self, campaign_id: u64) -> Promise {
+        let deposit = env::attached_deposit();
+        let buyer_id = env::predecessor_account_id();
+        self.assert_not_banned(&buyer_id);
+        let mut campaign = self
+            .campaigns
+            .get(&campaign_id)
+            .expect("Not found campaign");
+        assert!(campaign.remaining() > 0, "Campaign is sold out");
+        let total_price = campaign.price.0 + campaign.mint_deposit.0;
+        assert!(
+            deposit >= total_price && deposit > 0,
+            "Attached deposit must be greater than or equal current price: {}",
+            total_price
+        );
+
+        let serial = campaign.next_serial;
+        campaign.next_serial += 1;
+        campaign.pending += 1;
+        self.campaigns.insert(&campaign_id, &campaign);
+
+        if deposit > total_price {
+            Promise::new(buyer_id.clone()).transfer(deposit - total_price);
+        }
+
+        let template = campaign.metadata;
+        ext_nft_mint::nft_mint(
+            format!("{}-{}", campaign_id, serial),
+            TokenMetadata {
+                title: Some(format!("{} #{}", template.title, serial)),
+                description: template.description,
+                media: template.media,
+                copies: Some(campaign.supply),
+                issued_at: Some(env::block_timestamp().to_string()),
+                reference: template.reference,
+                extra: template.extra,
+            },
+            buyer_id.clone(),
+            &campaign.nft_contract_id,
+            campaign.mint_deposit.0,
+            GAS_FOR_NFT_MINT,
+        )
+        .then(ext_self::resolve_mint(
+            campaign_id,
+            buyer_id,
+            campaign.price,
+            campaign.mint_deposit,
+            &env::current_account_id(),
+            NO_DEPOSIT,
+            GAS_FOR_RESOLVE_MINT,
+        ))
+    }
+
+    /**
+    Pays the creator once the token is minted. A failed mint refunds the buyer; its serial
+    is not reused, as the child contract may have kept the token id.
+     */
+    #[private]
+    pub fn resolve_mint(
+        &mut self,
+        campaign_id: u64,
+        buyer_id: AccountId,
+        price: U128,
+        mint_deposit: U128,
+    ) -> bool {
+        let mut campaign = self
+            .campaigns
+            .get(&campaign_id)
+            .expect("Not found campaign");
+        campaign.pending -= 1;
+        let minted = is_promise_success();
+        if minted {
+            campaign.minted += 1;
+            Promise::new(campaign.creator_id.clone()).transfer(price.0);
+        } else {
+            env::log("Mint failed, refunding buyer".as_bytes());
+            Promise::new(buyer_id).transfer(price.0 + mint_deposit.0);
+        }
+        self.campaigns.insert(&campaign_id, &campaign);
+        minted
+    }
+}
@@ -0,0 +1,21 @@
+use crate::*;
+
+#[near_bindgen]
+impl Contract {
+    pub fn get_supply_campaigns(&self) -> U128 {
+        U128(self.campaigns.len() as u128)
+    }
+
+    pub fn get_campaign(&self, campaign_id: u64) -> Option<Campaign> {
+        self.campaigns.get(&campaign_id)
+    }
+
+    /// Campaigns in id order, pass `next_key` of the previous page as `from_key`.
+    pub fn get_campaigns(&self, from_key: Option<U64>, limit: Option<U64>) -> Page<Campaign, U64> {
+        filtered_page(
+            ids_after(from_key, self.next_campaign_id),
+            limit,
+            |campaign_id| self.campaigns.get(&campaign_id.0),
+        )
+    }
+}
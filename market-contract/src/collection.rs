@@ -0,0 +1,129 @@
+use crate::*;
+
+#[derive(BorshSerialize, BorshDeserialize, Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub enum ListingPolicy {
+    // Any NFT contract can list
+    Open,
+    // Only collections registered as `Allowed` can list
+    AllowList,
+    // Every NFT contract except collections registered as `Denied` can list
+    DenyList,
+}
+
+#[derive(BorshSerialize, BorshDeserialize, Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub enum CollectionStatus {
+    Allowed,
+    Denied,
+}
+
+#[derive(BorshSerialize, BorshDeserialize, Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub enum TransferStandard {
+    // NEP-199 `nft_transfer_payout`
+    PayoutOnTransfer,
+    // NEP-171 `nft_transfer` followed by NEP-199 `nft_payout`
+    PayoutAfterTransfer,
+    // NEP-171 `nft_transfer` only, the seller is paid in full
+    NoPayout,
+}
+
+#[derive(BorshSerialize, BorshDeserialize, Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub enum OverCapPolicy {
+    // Scale every royalty down proportionally to fit the cap
+    Clamp,
+    // Check `nft_payout` before the transfer and refund the buyer when it is over the cap
+    Reject,
+    // Pay the largest royalties first until the cap is used up
+    PaySellerRemainder,
+}
+
+#[derive(BorshSerialize, BorshDeserialize, Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct Collection {
+    pub name: String,
+    pub verified: bool,
+    pub status: CollectionStatus,
+    // Max total royalty in basis points (10000 = 100%)
+    pub royalty_cap_bps: Option<u32>,
+    // Unset fields fall back to the defaults in `payout.rs`
+    pub max_len_payout: Option<u32>,
+    pub rounding_tolerance: Option<U128>,
+    pub over_cap_policy: Option<OverCapPolicy>,
+}
+
+#[near_bindgen]
+impl Contract {
+    #[payable]
+    pub fn add_curator(&mut self, account_id: AccountId) {
+        assert_one_yocto();
+        self.assert_owner();
+        self.curators.insert(&account_id);
+    }
+
+    #[payable]
+    pub fn remove_curator(&mut self, account_id: AccountId) {
+        assert_one_yocto();
+        self.assert_owner();
+        self.curators.remove(&account_id);
+    }
+
+    #[payable]
+    pub fn set_listing_policy(&mut self, listing_policy: ListingPolicy) {
+        assert_one_yocto();
+        self.assert_owner();
+        self.listing_policy = listing_policy;
+    }
+
+    #[payable]
+    pub fn set_collection(&mut self, nft_contract_id: NFTContractId, collection: Collection) {
+        assert_one_yocto();
+        self.assert_curator();
+        if let Some(royalty_cap_bps) = collection.royalty_cap_bps {
+            assert!(
+                royalty_cap_bps <= 10_000,
+                "Royalty cap must not exceed 10000 basis points"
+            );
+        }
+        assert_ne!(
+            collection.max_len_payout,
+            Some(0),
+            "Max payout length must be greater than 0"
+        );
+        self.collections.insert(&nft_contract_id, &collection);
+    }
+
+    #[payable]
+    pub fn remove_collection(&mut self, nft_contract_id: NFTContractId) {
+        assert_one_yocto();
+        self.assert_curator();
+        self.collections
+            .remove(&nft_contract_id)
+            .expect("Not found collection");
+    }
+
+    /**
+    Pins how purchases settle on `nft_contract_id`. Contracts without `nft_transfer_payout`
+    can only be traded once pinned. `None` clears it so `nft_transfer_payout` is tried again.
+     */
+    #[payable]
+    pub fn set_transfer_standard(
+        &mut self,
+        nft_contract_id: NFTContractId,
+        transfer_standard: Option<TransferStandard>,
+    ) {
+        assert_one_yocto();
+        self.assert_curator();
+        match transfer_standard {
+            Some(transfer_standard) => {
+                self.transfer_standards
+                    .insert(&nft_contract_id, &transfer_standard);
+            }
+            None => {
+                self.transfer_standards.remove(&nft_contract_id);
+            }
+        }
+    }
+}
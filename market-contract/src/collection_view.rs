@@ -0,0 +1,40 @@
+use crate::*;
+
+#[near_bindgen]
+impl Contract {
+    pub fn get_collection(&self, nft_contract_id: NFTContractId) -> Option<Collection> {
+        self.collections.get(&nft_contract_id)
+    }
+
+    /// Collections in NFT contract order, pass `next_key` of the previous page as `from_key`.
+    pub fn get_collections(
+        &self,
+        from_key: Option<NFTContractId>,
+        limit: Option<U64>,
+    ) -> Page<(NFTContractId, Collection), NFTContractId> {
+        filtered_page(
+            index_keys_after(&self.collections, from_key),
+            limit,
+            |nft_contract_id| {
+                self.collections
+                    .get(nft_contract_id)
+                    .map(|collection| (nft_contract_id.clone(), collection))
+            },
+        )
+    }
+
+    pub fn get_transfer_standard(
+        &self,
+        nft_contract_id: NFTContractId,
+    ) -> Option<TransferStandard> {
+        self.transfer_standards.get(&nft_contract_id)
+    }
+
+    pub fn get_listing_policy(&self) -> ListingPolicy {
+        self.listing_policy
+    }
+
+    pub fn get_curators(&self) -> Vec<AccountId> {
+        self.curators.to_vec()
+    }
+}
@@ -1,18 +1,313 @@
 use crate::*;
 
-const INITIAL_BALANCE: Balance = 3_000_000_000_000_000_000_000_000; // 3e24yN, 3N
+// Account record and access key stored by the child next to its code
+const ACCOUNT_STORAGE_OVERHEAD: u64 = 1_000;
+const GAS_FOR_RESOLVE_CREATE_CHILD: Gas = 20_000_000_000_000;
+const GAS_FOR_CHILD_INIT: Gas = 50_000_000_000_000;
+const GAS_FOR_RESOLVE_UPGRADE_CHILD: Gas = 15_000_000_000_000;
+const GAS_FOR_UPGRADE_CHILD_CALL: Gas = 10_000_000_000_000;
+// Least the child's `upgrade` gets to deploy the code and migrate
+const MIN_GAS_FOR_CHILD_UPGRADE: Gas = 50_000_000_000_000;
+const GAS_FOR_CHILD_CLEANUP: Gas = 50_000_000_000_000;
+const GAS_FOR_CHILD_DELETE: Gas = 30_000_000_000_000;
+const GAS_FOR_RESOLVE_DELETE_CHILD: Gas = 15_000_000_000_000;
+
+/// Access key the signer gets on a child contract.
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub enum KeyPolicy {
+    FullAccess,
+    // Key limited to calling `method_names` (all methods when empty) on the child itself
+    FunctionCall {
+        method_names: Vec<String>,
+        // Unlimited when not set
+        allowance: Option<U128>,
+    },
+    None,
+}
+
+#[ext_contract(ext_self)]
+pub trait DeployResolver {
+    fn resolve_create_child(
+        &mut self,
+        creator_id: AccountId,
+        deployment: DeployedSmartContract,
+        deposit: U128,
+        fee: Option<DeploymentFee>,
+    ) -> bool;
+    fn resolve_upgrade_child(
+        &mut self,
+        creator_id: AccountId,
+        contract_id: AccountId,
+        template_version: String,
+    ) -> bool;
+    fn resolve_delete_child(&mut self, creator_id: AccountId, contract_id: AccountId) -> bool;
+}
+
+/// Balance a child needs to pay for the storage of `code_size` bytes of code.
+fn child_minimum_balance(code_size: u64) -> Balance {
+    Balance::from(code_size + ACCOUNT_STORAGE_OVERHEAD) * STORAGE_PRICE_PER_BYTE
+}
+
+/// Sets `owner_id` of the JSON object `args` to the creator, so nobody else can own the child.
+fn init_args_with_owner(args: &str, creator_id: &AccountId) -> Vec<u8> {
+    let mut args: near_sdk::serde_json::Value =
+        near_sdk::serde_json::from_str(args).expect("Init args must be JSON");
+    args.as_object_mut()
+        .expect("Init args must be a JSON object")
+        .insert("owner_id".to_string(), creator_id.clone().into());
+    args.to_string().into_bytes()
+}
 
 #[near_bindgen]
 impl Contract {
+    /**
+    Creates `prefix`.<market account> with `code` deployed, funded by the attached deposit.
+    The deposit must cover the child's storage plus `STORAGE_PER_SALE` the market keeps for
+    the deployment record, which is only written once the child is created.
+    With `init_method` the child is initialized in the same batch with `init_args` (a JSON object,
+    `owner_id` set to the caller), so a failing init reverts the whole deployment.
+     */
+    #[payable]
+    pub fn create_child_contract(
+        &mut self,
+        prefix: String,
+        code: Vec<u8>,
+        key_policy: Option<KeyPolicy>,
+        init_method: Option<String>,
+        init_args: Option<String>,
+        frontend_address: String,
+        contract_name: String,
+    ) -> Promise {
+        let init = init_method
+            .map(|method_name| (method_name, init_args.unwrap_or_else(|| "{}".to_string())));
+        self.internal_create_child(
+            prefix,
+            code,
+            key_policy,
+            init,
+            None,
+            frontend_address,
+            contract_name,
+        )
+    }
+
+    /// Records the child and accrues the fee when it was created and initialized,
+    /// otherwise refunds the creator.
     #[private]
-    pub fn create_child_contract(prefix: AccountId, code: Vec<u8>) -> Promise {
-        let subaccount_id =
-            AccountId::try_from(format!("{}.{}", prefix, env::current_account_id()));
-        Promise::new(subaccount_id.unwrap())
-            .create_account()
-            .add_full_access_key(env::signer_account_pk())
-            .transfer(INITIAL_BALANCE)
-            .deploy_contract(code.to_vec())
+    pub fn resolve_create_child(
+        &mut self,
+        creator_id: AccountId,
+        deployment: DeployedSmartContract,
+        deposit: U128,
+        fee: Option<DeploymentFee>,
+    ) -> bool {
+        if !is_promise_success() {
+            env::log(
+                format!(
+                    "Failed to create {}, refunding {}",
+                    deployment.contract_deploy_address, creator_id
+                )
+                .as_bytes(),
+            );
+            if let Some(DeploymentFee::FungibleToken { token_id, amount }) = fee {
+                self.internal_credit_fee_deposit(&creator_id, &token_id, amount.0);
+            }
+            Promise::new(creator_id).transfer(deposit.0);
+            return false;
+        }
+        if let Some(fee) = fee {
+            self.internal_accrue_fee(&fee);
+        }
+        self.internal_add_deployment(deployment);
+        true
+    }
+
+    /**
+    Redeploys a child created from a template with a newer version of it. The child must
+    expose `upgrade`, taking the wasm as raw input from the market and calling its `migrate`,
+    like the market's own `upgrade`.
+     */
+    #[payable]
+    pub fn upgrade_child(&mut self, contract_id: AccountId, template_version: String) -> Promise {
+        assert_one_yocto();
+        let creator_id = env::predecessor_account_id();
+        let deployment = self
+            .internal_find_deployment(&creator_id, &contract_id)
+            .expect("Not found deployment of creator");
+        assert!(
+            deployment.status == DeploymentStatus::Active,
+            "Deployment was deleted"
+        );
+        let template_id = deployment
+            .template_id
+            .expect("Deployment was not created from a template");
+        let template = self
+            .templates
+            .get(&template_id)
+            .expect("Not found template");
+        let version = template
+            .version(&template_version)
+            .expect("Not found template version");
+        if let Some(current_version) = deployment.template_version.as_ref() {
+            assert!(
+                parse_version(&template_version) > parse_version(current_version),
+                "Version must be greater than {}",
+                current_version
+            );
+        }
+        let code = self
+            .template_code
+            .get(&version.code_hash.into())
+            .expect("Not found template code");
+
+        let gas_needed = env::used_gas()
+            + GAS_FOR_RESOLVE_UPGRADE_CHILD
+            + GAS_FOR_UPGRADE_CHILD_CALL
+            + MIN_GAS_FOR_CHILD_UPGRADE;
+        assert!(
+            env::prepaid_gas() >= gas_needed,
+            "Require at least {} prepaid gas",
+            gas_needed
+        );
+        let upgrade_gas = env::prepaid_gas()
+            - env::used_gas()
+            - GAS_FOR_RESOLVE_UPGRADE_CHILD
+            - GAS_FOR_UPGRADE_CHILD_CALL;
+        Promise::new(contract_id.clone())
+            .function_call(b"upgrade".to_vec(), code, NO_DEPOSIT, upgrade_gas)
+            .then(ext_self::resolve_upgrade_child(
+                creator_id,
+                contract_id,
+                template_version,
+                &env::current_account_id(),
+                NO_DEPOSIT,
+                GAS_FOR_RESOLVE_UPGRADE_CHILD,
+            ))
+    }
+
+    /// Records the new version once the child upgraded and migrated.
+    #[private]
+    pub fn resolve_upgrade_child(
+        &mut self,
+        creator_id: AccountId,
+        contract_id: AccountId,
+        template_version: String,
+    ) -> bool {
+        if !is_promise_success() {
+            env::log(format!("Failed to upgrade {}", contract_id).as_bytes());
+            return false;
+        }
+        if let Some(mut deployment) = self.internal_find_deployment(&creator_id, &contract_id) {
+            deployment.template_version = Some(template_version);
+            self.deployments.insert(&contract_id, &deployment);
+        }
+        true
+    }
+
+    /**
+    Retires a child in one batch: its `cleanup` method clears its state, then `delete_self`
+    deletes the account with the creator as beneficiary. Both must accept calls from the market,
+    and `delete_self` must return the `delete_account` promise so the outcome reaches the market.
+     */
+    #[payable]
+    pub fn delete_child(&mut self, contract_id: AccountId) -> Promise {
+        assert_one_yocto();
+        let creator_id = env::predecessor_account_id();
+        let deployment = self
+            .internal_find_deployment(&creator_id, &contract_id)
+            .expect("Not found deployment of creator");
+        assert!(
+            deployment.status == DeploymentStatus::Active,
+            "Deployment was deleted"
+        );
+        let delete_args = near_sdk::serde_json::json!({ "beneficiary_id": creator_id });
+        Promise::new(contract_id.clone())
+            .function_call(
+                b"cleanup".to_vec(),
+                vec![],
+                NO_DEPOSIT,
+                GAS_FOR_CHILD_CLEANUP,
+            )
+            .function_call(
+                b"delete_self".to_vec(),
+                delete_args.to_string().into_bytes(),
+                NO_DEPOSIT,
+                GAS_FOR_CHILD_DELETE,
+            )
+            .then(ext_self::resolve_delete_child(
+                creator_id,
+                contract_id,
+                &env::current_account_id(),
+                NO_DEPOSIT,
+                GAS_FOR_RESOLVE_DELETE_CHILD,
+            ))
+    }
+
+    /// Marks the deployment deleted once the child account is gone.
+    #[private]
+    pub fn resolve_delete_child(&mut self, creator_id: AccountId, contract_id: AccountId) -> bool {
+        if !is_promise_success() {
+            env::log(format!("Failed to delete {}", contract_id).as_bytes());
+            return false;
+        }
+        if let Some(mut deployment) = self.internal_find_deployment(&creator_id, &contract_id) {
+            deployment.status = DeploymentStatus::Deleted;
+            self.deployments.insert(&contract_id, &deployment);
+        }
+        true
+    }
+
+    /// Lets the creator edit how a deployment is presented, paying for any extra storage.
+    #[payable]
+    pub fn update_deployment_metadata(
+        &mut self,
+        contract_id: AccountId,
+        frontend_address: Option<String>,
+        contract_name: Option<String>,
+        description: Option<String>,
+        icon: Option<String>,
+        tags: Option<Vec<String>>,
+    ) {
+        assert!(
+            env::attached_deposit() >= 1,
+            "Require attached deposit of at least 1 yoctoNear"
+        );
+        let initial_storage_usage = env::storage_usage();
+        let creator_id = env::predecessor_account_id();
+        let mut deployment = self
+            .internal_find_deployment(&creator_id, &contract_id)
+            .expect("Not found deployment of creator");
+        if let Some(frontend_address) = frontend_address {
+            deployment.frontend_address = frontend_address;
+        }
+        if let Some(contract_name) = contract_name {
+            deployment.contract_name = contract_name;
+        }
+        if description.is_some() {
+            deployment.description = description;
+        }
+        if icon.is_some() {
+            deployment.icon = icon;
+        }
+        if let Some(tags) = tags {
+            deployment.tags = tags;
+        }
+        self.deployments.insert(&contract_id, &deployment);
+        refund_deposit(
+            env::storage_usage().saturating_sub(initial_storage_usage),
+            creator_id,
+        );
+    }
+
+    /// Forgets a deployment, the child account itself is left untouched.
+    #[payable]
+    pub fn remove_deployment(&mut self, contract_id: AccountId) {
+        assert_one_yocto();
+        let creator_id = env::predecessor_account_id();
+        self.internal_find_deployment(&creator_id, &contract_id)
+            .expect("Not found deployment of creator");
+        self.internal_remove_deployment(&contract_id);
     }
 
     #[private]
@@ -23,44 +318,301 @@ impl Contract {
         frontend_address: String,
         contract_name: String,
     ) {
-        let deployed_smart_contract = DeployedSmartContract {
+        self.internal_add_deployment(DeployedSmartContract {
             contract_deploy_address,
+            creator_id,
             frontend_address,
             contract_name,
+            template_id: None,
+            template_version: None,
+            created_at: U64(env::block_timestamp()),
+            status: DeploymentStatus::Active,
+            description: None,
+            icon: None,
+            tags: vec![],
+        });
+    }
+
+    /// Deposit `create_child_contract` requires for code of `code_size` bytes.
+    pub fn get_child_minimum_deposit(&self, code_size: u64) -> U128 {
+        U128(child_minimum_balance(code_size) + STORAGE_PER_SALE)
+    }
+
+    /**
+    Deployments in address order, pass `next_key` of the previous page as `from_key`.
+    V1 records of the creator are included before they are migrated.
+     */
+    pub fn get_created_contract_by_creator(
+        &self,
+        creator_id: AccountId,
+        from_key: Option<AccountId>,
+        limit: Option<U64>,
+    ) -> Page<DeployedSmartContract, AccountId> {
+        let limit = page_limit(limit);
+        let mut deployments: Vec<DeployedSmartContract> = self
+            .legacy_deployments(&creator_id)
+            .into_iter()
+            .filter(|deployment| match &from_key {
+                Some(from_key) => &deployment.contract_deploy_address > from_key,
+                None => true,
+            })
+            .collect();
+        if let Some(by_creator_id) = self.creates.get(&creator_id) {
+            deployments.extend(
+                index_keys_after(&by_creator_id, from_key)
+                    .take(limit)
+                    .map(|contract_id| self.deployments.get(&contract_id).unwrap()),
+            );
+        }
+        deployments.sort_by(|a, b| a.contract_deploy_address.cmp(&b.contract_deploy_address));
+        deployments.truncate(limit);
+        let next_key = if deployments.len() == limit {
+            deployments
+                .last()
+                .map(|deployment| deployment.contract_deploy_address.clone())
+        } else {
+            None
+        };
+        Page {
+            items: deployments,
+            next_key,
+        }
+    }
+}
+
+impl Contract {
+    /// Creates and funds the child, calling `init` (method name and JSON args) in the same batch.
+    pub(crate) fn internal_create_child(
+        &mut self,
+        prefix: String,
+        code: Vec<u8>,
+        key_policy: Option<KeyPolicy>,
+        init: Option<(String, String)>,
+        template: Option<(String, String)>,
+        frontend_address: String,
+        contract_name: String,
+    ) -> Promise {
+        let creator_id = env::predecessor_account_id();
+        self.assert_not_banned(&creator_id);
+        let deposit = env::attached_deposit();
+        let fee = template
+            .as_ref()
+            .and_then(|(template_id, _)| self.templates.get(template_id))
+            .and_then(|template| template.fee);
+        let near_fee = match &fee {
+            Some(DeploymentFee::Near { amount }) => amount.0,
+            Some(DeploymentFee::FungibleToken { token_id, amount }) => {
+                self.internal_charge_fee_deposit(&creator_id, token_id, amount.0);
+                0
+            }
+            None => 0,
+        };
+        let required_deposit =
+            child_minimum_balance(code.len() as u64) + STORAGE_PER_SALE + near_fee;
+        assert!(
+            deposit >= required_deposit,
+            "Require deposit minimum of {}",
+            required_deposit
+        );
+        let subaccount_id = format!("{}.{}", prefix, env::current_account_id());
+        assert!(
+            !prefix.contains('.') && env::is_valid_account_id(subaccount_id.as_bytes()),
+            "Invalid prefix {}",
+            prefix
+        );
+
+        let mut child = Promise::new(subaccount_id.clone())
+            .create_account()
+            .transfer(deposit - STORAGE_PER_SALE - near_fee)
+            .deploy_contract(code);
+        child = match key_policy.unwrap_or(KeyPolicy::FullAccess) {
+            KeyPolicy::FullAccess => child.add_full_access_key(env::signer_account_pk()),
+            KeyPolicy::FunctionCall {
+                method_names,
+                allowance,
+            } => child.add_access_key(
+                env::signer_account_pk(),
+                allowance.map(|allowance| allowance.0).unwrap_or(0),
+                subaccount_id.clone(),
+                method_names.join(",").into_bytes(),
+            ),
+            KeyPolicy::None => child,
         };
+        if let Some((method_name, args)) = init {
+            child = child.function_call(
+                method_name.into_bytes(),
+                init_args_with_owner(&args, &creator_id),
+                NO_DEPOSIT,
+                GAS_FOR_CHILD_INIT,
+            );
+        }
+        let (template_id, template_version) = template.unzip();
+        child.then(ext_self::resolve_create_child(
+            creator_id.clone(),
+            DeployedSmartContract {
+                contract_deploy_address: subaccount_id,
+                creator_id,
+                frontend_address,
+                contract_name,
+                template_id,
+                template_version,
+                created_at: U64(env::block_timestamp()),
+                status: DeploymentStatus::Active,
+                description: None,
+                icon: None,
+                tags: vec![],
+            },
+            U128(deposit),
+            fee,
+            &env::current_account_id(),
+            NO_DEPOSIT,
+            GAS_FOR_RESOLVE_CREATE_CHILD,
+        ))
+    }
 
-        let mut creates = self.creates.get(&creator_id).unwrap_or_else(|| {
-            UnorderedSet::new(
-                StorageKey::InnerByCreatorIdKey {
-                    account_id_hash: hash_account_id(&creator_id),
+    /// Records a deployment under its address and in its creator's list. A record left at the
+    /// same address, e.g. by a deleted child, is removed from the indexes first.
+    pub(crate) fn internal_add_deployment(&mut self, deployment: DeployedSmartContract) {
+        if self
+            .deployments
+            .contains_key(&deployment.contract_deploy_address)
+        {
+            self.internal_remove_deployment(&deployment.contract_deploy_address);
+        }
+        let creator_id = &deployment.creator_id;
+        self.internal_migrate_creates(creator_id);
+        let mut creates = self.creates.get(creator_id).unwrap_or_else(|| {
+            TreeMap::new(
+                StorageKey::InnerCreatesByAddressKey {
+                    account_id_hash: hash_account_id(creator_id),
                 }
                 .try_to_vec()
                 .unwrap(),
             )
         });
+        creates.insert(&deployment.contract_deploy_address, &());
+        self.creates.insert(creator_id, &creates);
 
-        creates.insert(&deployed_smart_contract);
-        self.creates.insert(&creator_id, &creates);
+        if let Some(template_id) = &deployment.template_id {
+            let mut by_template_id = self
+                .deployments_by_template_id
+                .get(template_id)
+                .unwrap_or_else(|| {
+                    TreeMap::new(
+                        StorageKey::InnerDeploymentsByTemplateIdKey {
+                            template_id_hash: hash_account_id(template_id),
+                        }
+                        .try_to_vec()
+                        .unwrap(),
+                    )
+                });
+            by_template_id.insert(&deployment.contract_deploy_address, &());
+            self.deployments_by_template_id
+                .insert(template_id, &by_template_id);
+        }
+        self.deployments
+            .insert(&deployment.contract_deploy_address, &deployment);
     }
 
-    pub fn get_created_contract_by_creator(
-        &self,
-        creator_id: AccountId,
-        from_index: Option<u128>,
-        limit: Option<u64>,
-    ) -> Vec<DeployedSmartContract> {
-        let by_creator_id = self.creates.get(&creator_id);
-        let deploy_smart_contracts = if let Some(by_creator_id) = by_creator_id {
-            by_creator_id
-        } else {
-            return vec![];
-        };
-        let start = u128::from(from_index.unwrap_or(0));
-        deploy_smart_contracts
-            .as_vector()
-            .iter()
-            .skip(start as usize)
-            .take(limit.unwrap_or(0) as usize)
-            .collect()
+    pub(crate) fn internal_remove_deployment(
+        &mut self,
+        contract_id: &AccountId,
+    ) -> DeployedSmartContract {
+        let deployment = self
+            .deployments
+            .remove(contract_id)
+            .expect("Not found deployment");
+        if let Some(mut creates) = self.creates.get(&deployment.creator_id) {
+            creates.remove(contract_id);
+            if creates.len() == 0 {
+                self.creates.remove(&deployment.creator_id);
+            } else {
+                self.creates.insert(&deployment.creator_id, &creates);
+            }
+        }
+        if let Some(template_id) = &deployment.template_id {
+            if let Some(mut by_template_id) = self.deployments_by_template_id.get(template_id) {
+                by_template_id.remove(contract_id);
+                if by_template_id.len() == 0 {
+                    self.deployments_by_template_id.remove(template_id);
+                } else {
+                    self.deployments_by_template_id
+                        .insert(template_id, &by_template_id);
+                }
+            }
+        }
+        deployment
+    }
+
+    /// Migrates the creator's V1 records first, so they can be found too.
+    pub(crate) fn internal_find_deployment(
+        &mut self,
+        creator_id: &AccountId,
+        contract_id: &AccountId,
+    ) -> Option<DeployedSmartContract> {
+        self.internal_migrate_creates(creator_id);
+        self.deployments
+            .get(contract_id)
+            .filter(|deployment| &deployment.creator_id == creator_id)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use near_sdk::test_utils::{accounts, VMContextBuilder};
+    use near_sdk::{testing_env, MockedBlockchain};
+
+    fn deployment(creator_id: AccountId, template_id: &str) -> DeployedSmartContract {
+        DeployedSmartContract {
+            contract_deploy_address: format!("child.{}", accounts(0)),
+            creator_id,
+            frontend_address: String::new(),
+            contract_name: "child".to_string(),
+            template_id: Some(template_id.to_string()),
+            template_version: Some("1".to_string()),
+            created_at: U64(0),
+            status: DeploymentStatus::Active,
+            description: None,
+            icon: None,
+            tags: vec![],
+        }
+    }
+
+    #[test]
+    fn recreated_child_replaces_deleted_record() {
+        testing_env!(VMContextBuilder::new()
+            .current_account_id(accounts(0))
+            .build());
+        let mut contract = Contract::new(accounts(0).into());
+        let mut deleted = deployment(accounts(1).into(), "first");
+        deleted.status = DeploymentStatus::Deleted;
+        contract.internal_add_deployment(deleted);
+
+        contract.internal_add_deployment(deployment(accounts(2).into(), "second"));
+        assert_eq!(contract.get_supply_deployments().0, 1);
+        assert!(contract
+            .get_created_contract_by_creator(accounts(1).into(), None, None)
+            .items
+            .is_empty());
+        assert_eq!(
+            contract
+                .get_created_contract_by_creator(accounts(2).into(), None, None)
+                .items
+                .len(),
+            1
+        );
+        assert_eq!(
+            contract
+                .get_supply_deployments_by_template("first".to_string())
+                .0,
+            0
+        );
+        assert_eq!(
+            contract
+                .get_supply_deployments_by_template("second".to_string())
+                .0,
+            1
+        );
     }
 }
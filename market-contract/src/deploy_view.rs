@@ -0,0 +1,69 @@
+use crate::*;
+
+#[near_bindgen]
+impl Contract {
+    pub fn get_supply_deployments(&self) -> U128 {
+        U128(self.deployments.len() as u128)
+    }
+
+    pub fn get_supply_deployments_by_creator(&self, creator_id: AccountId) -> U128 {
+        let legacy = self
+            .legacy_creates
+            .get(&creator_id)
+            .map(|legacy_creates| legacy_creates.len())
+            .unwrap_or(0);
+        if let Some(creates) = self.creates.get(&creator_id) {
+            U128((creates.len() + legacy) as u128)
+        } else {
+            U128(legacy as u128)
+        }
+    }
+
+    pub fn get_supply_deployments_by_template(&self, template_id: String) -> U128 {
+        if let Some(by_template_id) = self.deployments_by_template_id.get(&template_id) {
+            U128(by_template_id.len() as u128)
+        } else {
+            U128(0)
+        }
+    }
+
+    pub fn get_deployment(&self, contract_address: AccountId) -> Option<DeployedSmartContract> {
+        self.deployments.get(&contract_address)
+    }
+
+    /// Deployments in address order, pass `next_key` of the previous page as `from_key`.
+    pub fn get_all_deployments(
+        &self,
+        from_key: Option<AccountId>,
+        limit: Option<U64>,
+    ) -> Page<DeployedSmartContract, AccountId> {
+        filtered_page(
+            index_keys_after(&self.deployments, from_key),
+            limit,
+            |contract_id| self.deployments.get(contract_id),
+        )
+    }
+
+    /// Deployments in address order, pass `next_key` of the previous page as `from_key`.
+    pub fn get_deployments_by_template(
+        &self,
+        template_id: String,
+        from_key: Option<AccountId>,
+        limit: Option<U64>,
+    ) -> Page<DeployedSmartContract, AccountId> {
+        let by_template_id =
+            if let Some(by_template_id) = self.deployments_by_template_id.get(&template_id) {
+                by_template_id
+            } else {
+                return Page {
+                    items: vec![],
+                    next_key: None,
+                };
+            };
+        filtered_page(
+            index_keys_after(&by_template_id, from_key),
+            limit,
+            |contract_id| self.deployments.get(contract_id),
+        )
+    }
+}
@@ -0,0 +1,164 @@
+use crate::*;
+
+#[derive(BorshSerialize, BorshDeserialize, Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub enum DropPhase {
+    Upcoming,
+    // Only allowlisted accounts can buy
+    Allowlist,
+    Public,
+}
+
+#[derive(BorshSerialize, BorshDeserialize, Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct Drop {
+    pub drop_id: u64,
+    pub owner_id: AccountId,
+    // Block timestamps in nanoseconds
+    pub allowlist_starts_at: Option<U64>,
+    pub starts_at: U64,
+    pub allowlist: Vec<AccountId>,
+    pub per_wallet_limit: Option<u32>,
+}
+
+impl Drop {
+    pub fn phase(&self, now: u64) -> DropPhase {
+        if now >= self.starts_at.0 {
+            DropPhase::Public
+        } else if matches!(self.allowlist_starts_at, Some(allowlist_starts_at) if now >= allowlist_starts_at.0)
+        {
+            DropPhase::Allowlist
+        } else {
+            DropPhase::Upcoming
+        }
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    /**
+    Creates a drop that listings can join with `drop_id` in their approval msg.
+    The attached deposit pays for the storage of the drop, the rest is refunded.
+     */
+    #[payable]
+    pub fn create_drop(
+        &mut self,
+        starts_at: U64,
+        allowlist_starts_at: Option<U64>,
+        allowlist: Option<Vec<AccountId>>,
+        per_wallet_limit: Option<u32>,
+    ) -> u64 {
+        let initial_storage_usage = env::storage_usage();
+        let owner_id = env::predecessor_account_id();
+        self.assert_not_banned(&owner_id);
+        if let Some(allowlist_starts_at) = allowlist_starts_at {
+            assert!(
+                allowlist_starts_at.0 <= starts_at.0,
+                "Allowlist phase must start before the public phase"
+            );
+        }
+        assert_ne!(
+            per_wallet_limit,
+            Some(0),
+            "Per wallet limit must be greater than 0"
+        );
+
+        let drop_id = self.next_drop_id;
+        self.next_drop_id += 1;
+        self.drops.insert(
+            &drop_id,
+            &Drop {
+                drop_id,
+                owner_id: owner_id.clone(),
+                allowlist_starts_at,
+                starts_at,
+                allowlist: allowlist.unwrap_or_default(),
+                per_wallet_limit,
+            },
+        );
+
+        refund_deposit(env::storage_usage() - initial_storage_usage, owner_id);
+        drop_id
+    }
+
+    /// Removes a drop without sales left in it.
+    #[payable]
+    pub fn remove_drop(&mut self, drop_id: u64) {
+        assert_one_yocto();
+        let drop = self.drops.get(&drop_id).expect("Not found drop");
+        assert_eq!(
+            env::predecessor_account_id(),
+            drop.owner_id,
+            "Must be owner id"
+        );
+        assert!(
+            self.by_drop_id.get(&drop_id).is_none(),
+            "Drop still has sales"
+        );
+        self.drops.remove(&drop_id);
+    }
+}
+
+impl Contract {
+    /// Why `buyer_id` can not buy `sale` from its drop right now, if it can not.
+    pub(crate) fn drop_restriction(&self, sale: &Sale, buyer_id: &AccountId) -> Option<String> {
+        let drop_id = sale.drop_id?;
+        let drop = self.drops.get(&drop_id)?;
+        match drop.phase(env::block_timestamp()) {
+            DropPhase::Upcoming => return Some("Drop has not started".to_string()),
+            DropPhase::Allowlist if !drop.allowlist.contains(buyer_id) => {
+                return Some("Drop is only open to its allowlist".to_string())
+            }
+            _ => {}
+        }
+        match drop.per_wallet_limit {
+            Some(limit) if self.internal_drop_purchases(drop_id, buyer_id) >= limit => {
+                Some(format!("Drop allows {} purchases per wallet", limit))
+            }
+            _ => None,
+        }
+    }
+
+    pub(crate) fn internal_drop_purchases(&self, drop_id: u64, buyer_id: &AccountId) -> u32 {
+        self.drop_purchases
+            .get(&format!("{}{}{}", drop_id, DELIMETER, buyer_id))
+            .unwrap_or(0)
+    }
+
+    /// Counted when the purchase is dispatched, so purchases still settling count towards the limit.
+    pub(crate) fn internal_record_drop_purchase(&mut self, sale: &Sale, buyer_id: &AccountId) {
+        if let Some(drop_id) = sale.drop_id {
+            let purchases = self.internal_drop_purchases(drop_id, buyer_id);
+            self.drop_purchases.insert(
+                &format!("{}{}{}", drop_id, DELIMETER, buyer_id),
+                &(purchases + 1),
+            );
+        }
+    }
+
+    /// Gives back a purchase whose transfer failed and was refunded.
+    pub(crate) fn internal_release_drop_purchase(&mut self, sale: &Sale, buyer_id: &AccountId) {
+        if let Some(drop_id) = sale.drop_id {
+            let key = format!("{}{}{}", drop_id, DELIMETER, buyer_id);
+            match self.internal_drop_purchases(drop_id, buyer_id) {
+                0 => {}
+                1 => {
+                    self.drop_purchases.remove(&key);
+                }
+                purchases => {
+                    self.drop_purchases.insert(&key, &(purchases - 1));
+                }
+            }
+        }
+    }
+
+    /// Sales of a drop that has not reached its public phase are only live for their owner.
+    pub(crate) fn is_live_for(&self, sale: &Sale, viewer_id: Option<&AccountId>) -> bool {
+        let is_upcoming = sale
+            .drop_id
+            .and_then(|drop_id| self.drops.get(&drop_id))
+            .map(|drop| drop.phase(env::block_timestamp()) != DropPhase::Public)
+            .unwrap_or(false);
+        sale.is_visible_to(viewer_id) && (!is_upcoming || viewer_id == Some(&sale.owner_id))
+    }
+}
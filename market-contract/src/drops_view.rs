@@ -0,0 +1,59 @@
+use crate::*;
+
+#[near_bindgen]
+impl Contract {
+    pub fn get_drop(&self, drop_id: u64) -> Option<Drop> {
+        self.drops.get(&drop_id)
+    }
+
+    pub fn get_drop_phase(&self, drop_id: u64) -> DropPhase {
+        self.drops
+            .get(&drop_id)
+            .expect("Not found drop")
+            .phase(env::block_timestamp())
+    }
+
+    /// Drops whose public phase has not started yet, in id order. Pass `next_key` of the
+    /// previous page as `from_key`.
+    pub fn get_upcoming_drops(&self, from_key: Option<U64>, limit: Option<U64>) -> Page<Drop, U64> {
+        let now = env::block_timestamp();
+        filtered_page(ids_after(from_key, self.next_drop_id), limit, |drop_id| {
+            self.drops
+                .get(&drop_id.0)
+                .filter(|drop| drop.phase(now) != DropPhase::Public)
+        })
+    }
+
+    /// Sales of a drop in key order, also before the drop goes public. Sales reserved for
+    /// specific buyers are only returned when `viewer_id` may see them. Pass `next_key` of the
+    /// previous page as `from_key`.
+    pub fn get_sales_by_drop_id(
+        &self,
+        drop_id: u64,
+        from_key: Option<ContractAndTokenId>,
+        limit: Option<U64>,
+        viewer_id: Option<AccountId>,
+    ) -> Page<Sale, ContractAndTokenId> {
+        let by_drop_id = if let Some(by_drop_id) = self.by_drop_id.get(&drop_id) {
+            by_drop_id
+        } else {
+            return Page {
+                items: vec![],
+                next_key: None,
+            };
+        };
+        filtered_page(
+            index_keys_after(&by_drop_id, from_key),
+            limit,
+            |contract_and_token_id| {
+                self.sales
+                    .get(contract_and_token_id)
+                    .filter(|sale| sale.is_visible_to(viewer_id.as_ref()))
+            },
+        )
+    }
+
+    pub fn get_drop_purchases(&self, drop_id: u64, account_id: AccountId) -> u32 {
+        self.internal_drop_purchases(drop_id, &account_id)
+    }
+}
@@ -2,6 +2,156 @@ use crate::*;
 
 #[near_bindgen]
 impl Contract {
+    pub(crate) fn assert_owner(&self) {
+        assert_eq!(
+            env::predecessor_account_id(),
+            self.owner_id,
+            "Only contract owner can call this method"
+        );
+    }
+
+    pub(crate) fn assert_curator(&self) {
+        let account_id = env::predecessor_account_id();
+        assert!(
+            account_id == self.owner_id || self.curators.contains(&account_id),
+            "Only curators can call this method"
+        );
+    }
+
+    pub(crate) fn assert_admin(&self) {
+        let account_id = env::predecessor_account_id();
+        assert!(
+            account_id == self.owner_id || self.admins.contains(&account_id),
+            "Only admins can call this method"
+        );
+    }
+
+    pub(crate) fn assert_not_banned(&self, account_id: &AccountId) {
+        assert!(
+            !self.banned_accounts.contains_key(account_id),
+            "Account {} is banned",
+            account_id
+        );
+    }
+
+    pub(crate) fn assert_sale_purchasable(
+        &self,
+        contract_and_token_id: &ContractAndTokenId,
+        sale: &Sale,
+        buyer_id: &AccountId,
+    ) {
+        self.assert_not_banned(buyer_id);
+        assert_ne!(buyer_id, &sale.owner_id, "Can not bid on your own sale");
+        assert!(sale.can_buy(buyer_id), "Sale is reserved for other buyers");
+        assert!(
+            self.bundled_tokens.get(contract_and_token_id).is_none(),
+            "Token is only sold as part of a bundle"
+        );
+        if let Some(reason) = self.drop_restriction(sale, buyer_id) {
+            env::panic(reason.as_bytes());
+        }
+    }
+
+    pub(crate) fn assert_collection_listable(&self, nft_contract_id: &NFTContractId) {
+        let collection = self.collections.get(nft_contract_id);
+        let listable = match self.listing_policy {
+            ListingPolicy::Open => true,
+            ListingPolicy::AllowList => {
+                matches!(collection, Some(c) if c.status == CollectionStatus::Allowed)
+            }
+            ListingPolicy::DenyList => {
+                !matches!(collection, Some(c) if c.status == CollectionStatus::Denied)
+            }
+        };
+        assert!(listable, "NFT contract {} is not allowed", nft_contract_id);
+    }
+
+    /// Inserts or replaces a sale and adds it to the owner, contract and drop indexes.
+    pub(crate) fn internal_add_sale(&mut self, sale: &Sale) {
+        let contract_and_token_id =
+            format!("{}{}{}", sale.nft_contract_id, DELIMETER, sale.token_id);
+        if let Some(old_sale) = self.sales.insert(&contract_and_token_id, sale) {
+            // A new owner's listing goes to the end of the listing order
+            let keep_order = old_sale.listed_at == sale.listed_at;
+            self.internal_unindex_sale(&contract_and_token_id, &old_sale, keep_order);
+            if old_sale.owner_id != sale.owner_id {
+                self.internal_remove_from_owner(&old_sale.owner_id, &contract_and_token_id);
+            }
+            if old_sale.drop_id != sale.drop_id {
+                self.internal_remove_from_drop(&old_sale, &contract_and_token_id);
+            }
+        }
+        self.internal_index_sale(&contract_and_token_id, sale);
+        if let Some(drop_id) = sale.drop_id {
+            let mut by_drop_id = self.by_drop_id.get(&drop_id).unwrap_or_else(|| {
+                TreeMap::new(
+                    StorageKey::InnerByDropIdKey { drop_id }
+                        .try_to_vec()
+                        .unwrap(),
+                )
+            });
+            by_drop_id.insert(&contract_and_token_id, &());
+            self.by_drop_id.insert(&drop_id, &by_drop_id);
+        }
+
+        let mut sales_by_owner = self.sales_by_owner.get(&sale.owner_id).unwrap_or_else(|| {
+            TreeMap::new(
+                StorageKey::InnerSalesByOwnerKey {
+                    account_id_hash: hash_account_id(&sale.owner_id),
+                }
+                .try_to_vec()
+                .unwrap(),
+            )
+        });
+        sales_by_owner.insert(&contract_and_token_id, &());
+        self.sales_by_owner.insert(&sale.owner_id, &sales_by_owner);
+
+        let mut by_owner_id = self.by_owner_id.get(&sale.owner_id).unwrap_or_else(|| {
+            UnorderedSet::new(
+                StorageKey::InnerByOwnerIdKey {
+                    account_id_hash: hash_account_id(&sale.owner_id),
+                }
+                .try_to_vec()
+                .unwrap(),
+            )
+        });
+
+        by_owner_id.insert(&contract_and_token_id);
+        self.by_owner_id.insert(&sale.owner_id, &by_owner_id);
+
+        let mut by_contract_id = self
+            .by_contract_id
+            .get(&sale.nft_contract_id)
+            .unwrap_or_else(|| {
+                UnorderedSet::new(
+                    StorageKey::InnerByContractIdKey {
+                        account_id_hash: hash_account_id(&sale.nft_contract_id),
+                    }
+                    .try_to_vec()
+                    .unwrap(),
+                )
+            });
+        by_contract_id.insert(&sale.token_id);
+        self.by_contract_id
+            .insert(&sale.nft_contract_id, &by_contract_id);
+
+        let mut sales_by_contract = self
+            .sales_by_contract
+            .get(&sale.nft_contract_id)
+            .unwrap_or_else(|| {
+                TreeMap::new(
+                    StorageKey::InnerSalesByContractKey {
+                        account_id_hash: hash_account_id(&sale.nft_contract_id),
+                    }
+                    .try_to_vec()
+                    .unwrap(),
+                )
+            });
+        sales_by_contract.insert(&sale.token_id, &());
+        self.sales_by_contract
+            .insert(&sale.nft_contract_id, &sales_by_contract);
+    }
+
     pub(crate) fn internal_remove_sale(
         &mut self,
         nft_contract_id: AccountId,
@@ -12,17 +162,12 @@ impl Contract {
             .sales
             .remove(&contract_and_token_id)
             .expect("Not found sale");
-        let mut by_owner_id = self
-            .by_owner_id
-            .get(&sale.owner_id)
-            .expect("Not found sale by owner");
-        by_owner_id.remove(&contract_and_token_id);
-        self.by_owner_id.insert(&sale.owner_id, &by_owner_id);
-        if by_owner_id.is_empty() {
-            self.by_owner_id.remove(&sale.owner_id);
-        } else {
-            self.by_owner_id.insert(&sale.owner_id, &by_owner_id);
-        };
+        self.internal_unindex_sale(&contract_and_token_id, &sale, false);
+        if let Some(bundle_id) = self.bundled_tokens.get(&contract_and_token_id) {
+            self.internal_remove_bundle(bundle_id);
+        }
+        self.internal_remove_from_drop(&sale, &contract_and_token_id);
+        self.internal_remove_from_owner(&sale.owner_id, &contract_and_token_id);
 
         let mut by_contract_id = self
             .by_contract_id
@@ -37,52 +182,174 @@ impl Contract {
                 .insert(&nft_contract_id, &by_contract_id);
         }
 
+        if let Some(mut sales_by_contract) = self.sales_by_contract.get(&nft_contract_id) {
+            sales_by_contract.remove(&token_id);
+            if sales_by_contract.len() == 0 {
+                self.sales_by_contract.remove(&nft_contract_id);
+            } else {
+                self.sales_by_contract
+                    .insert(&nft_contract_id, &sales_by_contract);
+            }
+        }
+
         sale
     }
 
-    pub(crate) fn internal_remove_uses(
+    fn internal_remove_from_owner(
         &mut self,
+        owner_id: &AccountId,
+        contract_and_token_id: &ContractAndTokenId,
+    ) {
+        if let Some(mut sales_by_owner) = self.sales_by_owner.get(owner_id) {
+            sales_by_owner.remove(contract_and_token_id);
+            if sales_by_owner.len() == 0 {
+                self.sales_by_owner.remove(owner_id);
+            } else {
+                self.sales_by_owner.insert(owner_id, &sales_by_owner);
+            }
+        }
+        let mut by_owner_id = self
+            .by_owner_id
+            .get(owner_id)
+            .expect("Not found sale by owner");
+        by_owner_id.remove(contract_and_token_id);
+        if by_owner_id.is_empty() {
+            self.by_owner_id.remove(owner_id);
+        } else {
+            self.by_owner_id.insert(owner_id, &by_owner_id);
+        };
+    }
+
+    fn internal_remove_from_drop(
+        &mut self,
+        sale: &Sale,
+        contract_and_token_id: &ContractAndTokenId,
+    ) {
+        let drop_id = if let Some(drop_id) = sale.drop_id {
+            drop_id
+        } else {
+            return;
+        };
+        if let Some(mut by_drop_id) = self.by_drop_id.get(&drop_id) {
+            by_drop_id.remove(contract_and_token_id);
+            if by_drop_id.len() == 0 {
+                self.by_drop_id.remove(&drop_id);
+            } else {
+                self.by_drop_id.insert(&drop_id, &by_drop_id);
+            }
+        }
+    }
+
+    /// Removes a bundle and releases its tokens, whose sales stay listed.
+    pub(crate) fn internal_remove_bundle(&mut self, bundle_id: u64) -> Bundle {
+        let bundle = self.bundles.remove(&bundle_id).expect("Not found bundle");
+        for item in bundle.items.iter() {
+            self.bundled_tokens.remove(&format!(
+                "{}{}{}",
+                item.nft_contract_id, DELIMETER, item.token_id
+            ));
+        }
+        bundle
+    }
+
+    pub(crate) fn internal_update_price(
+        &mut self,
+        owner_id: &AccountId,
         nft_contract_id: AccountId,
         token_id: TokenId,
-    ) -> Uses {
+        price: U128,
+    ) {
         let contract_and_token_id = format!("{}{}{}", nft_contract_id, DELIMETER, token_id);
-        let uses = self
+        let mut sale = self
+            .sales
+            .get(&contract_and_token_id)
+            .expect("Not found sale");
+        assert_eq!(owner_id, &sale.owner_id, "Must be sale owner");
+        self.internal_unindex_sale(&contract_and_token_id, &sale, true);
+        sale.sale_conditions = price;
+        self.internal_index_sale(&contract_and_token_id, &sale);
+        self.sales.insert(&contract_and_token_id, &sale);
+    }
+
+    pub(crate) fn internal_update_use_price(
+        &mut self,
+        owner_id: &AccountId,
+        nft_contract_id: AccountId,
+        token_id: TokenId,
+        price: U128,
+    ) {
+        let contract_and_token_id = format!("{}{}{}", nft_contract_id, DELIMETER, token_id);
+        let mut uses = self
             .uses
-            .remove(&contract_and_token_id)
+            .get(&contract_and_token_id)
             .expect("Not found uses");
-        uses
+        assert_eq!(owner_id, &uses.owner_id, "Must be sale owner");
+        self.internal_unindex_uses(&contract_and_token_id, &uses, true);
+        uses.use_conditions = price;
+        self.internal_index_uses(&contract_and_token_id, &uses);
+        self.uses.insert(&contract_and_token_id, &uses);
     }
 
-    pub(crate) fn internal_payout(&mut self, buyer_id: AccountId, price: U128) -> U128 {
-        let payout_option = promise_result_as_success().and_then(|value| {
-            let payout_object =
-                near_sdk::serde_json::from_slice::<Payout>(&value).expect("Invalid payout object");
-            if payout_object.payout.len() > 10 || payout_object.payout.is_empty() {
-                env::log("Cannot have more than 10 royalities".as_bytes());
-                None
-            } else {
-                let mut remainder = price.0;
-                for &value in payout_object.payout.values() {
-                    remainder = remainder.checked_sub(value.0)?;
-                }
+    pub(crate) fn internal_add_uses(&mut self, uses: &Uses) {
+        let contract_and_token_id =
+            format!("{}{}{}", uses.nft_contract_id, DELIMETER, uses.token_id);
+        if let Some(old_uses) = self.uses.insert(&contract_and_token_id, uses) {
+            let keep_order = old_uses.listed_at == uses.listed_at;
+            self.internal_unindex_uses(&contract_and_token_id, &old_uses, keep_order);
+        }
+        self.internal_index_uses(&contract_and_token_id, uses);
+    }
 
-                if remainder == 0 || remainder == 1 {
-                    Some(payout_object.payout)
-                } else {
-                    None
-                }
-            }
-        });
+    pub(crate) fn internal_remove_uses(
+        &mut self,
+        nft_contract_id: &AccountId,
+        token_id: &TokenId,
+    ) -> Option<Uses> {
+        let contract_and_token_id = format!("{}{}{}", nft_contract_id, DELIMETER, token_id);
+        let uses = self.uses.remove(&contract_and_token_id)?;
+        self.internal_unindex_uses(&contract_and_token_id, &uses, false);
+        Some(uses)
+    }
+
+    pub(crate) fn internal_remove_listing(
+        &mut self,
+        nft_contract_id: AccountId,
+        token_id: TokenId,
+    ) {
+        let contract_and_token_id = format!("{}{}{}", nft_contract_id, DELIMETER, token_id);
+        self.internal_remove_uses(&nft_contract_id, &token_id);
+        if self.sales.get(&contract_and_token_id).is_some() {
+            self.internal_remove_sale(nft_contract_id, token_id);
+        }
+    }
 
-        let payout = if let Some(payout_option) = payout_option {
-            payout_option
+    /**
+    Pays out the result of `nft_transfer_payout`/`nft_use_payout`. The buyer is refunded only
+    when the call failed; once it succeeded the seller is paid in full if the payout is unusable.
+     */
+    pub(crate) fn internal_payout(
+        &mut self,
+        buyer_id: AccountId,
+        price: U128,
+        nft_contract_id: &NFTContractId,
+        seller_id: AccountId,
+    ) -> U128 {
+        let value = if let Some(value) = promise_result_as_success() {
+            value
         } else {
             Promise::new(buyer_id).transfer(u128::from(price));
             return price;
         };
 
-        for (receiver_id, amount) in payout {
-            Promise::new(receiver_id).transfer(amount.into());
+        match self
+            .payout_policy(nft_contract_id)
+            .apply(&value, price, &seller_id)
+        {
+            Some(payout) => pay_out(payout),
+            None => {
+                env::log("Invalid payout, paying seller in full".as_bytes());
+                Promise::new(seller_id).transfer(u128::from(price));
+            }
         }
         price
     }
@@ -1,26 +1,62 @@
+// `near_bindgen` only emits the exported wrappers on wasm32, and `ext_contract`
+// mirrors the foreign method signatures as-is.
+#![cfg_attr(not(target_arch = "wasm32"), allow(dead_code))]
+#![allow(clippy::too_many_arguments)]
+
 use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
-use near_sdk::collections::{LookupMap, UnorderedMap, UnorderedSet};
-use near_sdk::json_types::U128;
+use near_sdk::collections::{LookupMap, TreeMap, UnorderedMap, UnorderedSet};
+use near_sdk::json_types::{U128, U64};
 use near_sdk::serde::{Deserialize, Serialize};
 use near_sdk::{
-    env, env::STORAGE_PRICE_PER_BYTE, ext_contract, near_bindgen, promise_result_as_success,
-    AccountId, Balance, CryptoHash, Gas, PanicOnDefault, Promise,
+    env, env::STORAGE_PRICE_PER_BYTE, ext_contract, is_promise_success, near_bindgen,
+    promise_result_as_success, AccountId, Balance, CryptoHash, Gas, PanicOnDefault, Promise,
+    PromiseOrValue, PromiseResult,
 };
 
+use crate::bundle::*;
+use crate::campaign::*;
+use crate::collection::*;
 use crate::deploy::*;
-use crate::internal::*;
-use crate::nft_callback::*;
+use crate::drops::*;
+use crate::migrate::*;
+use crate::moderation::*;
+use crate::pagination::*;
+use crate::payout::*;
 use crate::sale::*;
-use crate::sale_view::*;
-use crate::uses::*;
-use crate::uses_view::*;
+use crate::search::*;
+use crate::swap::*;
+use crate::template::*;
+use crate::treasury::*;
 use crate::utils::*;
 
+mod bundle;
+mod bundle_view;
+mod campaign;
+mod campaign_view;
+mod collection;
+mod collection_view;
 mod deploy;
+mod deploy_view;
+mod drops;
+mod drops_view;
 mod internal;
+mod listing_view;
+mod migrate;
+mod moderation;
+mod moderation_view;
 mod nft_callback;
+mod pagination;
+mod payout;
 mod sale;
 mod sale_view;
+mod search;
+mod search_view;
+mod swap;
+mod swap_view;
+mod template;
+mod template_view;
+mod treasury;
+mod treasury_view;
 mod uses;
 mod uses_view;
 mod utils;
@@ -34,7 +70,8 @@ pub type SalePriceInYoctoNear = U128;
 pub type UsePriceInYoctoNear = U128;
 pub type ContractAndTokenId = String; // nft-tutorial.vbi.dev.testnet.VBI_NFT#01
 
-#[derive(BorshSerialize, BorshDeserialize, Serialize, Deserialize)]
+// Also read in the V1 layout, see `migrate_listings`
+#[derive(BorshSerialize, Serialize, Deserialize)]
 #[serde(crate = "near_sdk::serde")]
 pub struct Sale {
     pub owner_id: AccountId,
@@ -42,23 +79,47 @@ pub struct Sale {
     pub nft_contract_id: NFTContractId,
     pub token_id: TokenId,
     pub sale_conditions: SalePriceInYoctoNear,
+    // Only these accounts can buy when not empty
+    pub reserved_for: Vec<AccountId>,
+    // Drop gating when the sale can be bought
+    pub drop_id: Option<u64>,
+    // Block timestamp of the first listing by this owner
+    pub listed_at: U64,
 }
 
-#[derive(BorshSerialize, BorshDeserialize, Serialize, Deserialize)]
+// Also read in the V1 layout, see `migrate_listings`
+#[derive(BorshSerialize, Serialize, Deserialize)]
 #[serde(crate = "near_sdk::serde")]
 pub struct Uses {
     pub owner_id: AccountId,
     pub nft_contract_id: NFTContractId,
     pub token_id: TokenId,
     pub use_conditions: UsePriceInYoctoNear,
+    pub listed_at: U64,
 }
 
 #[derive(BorshSerialize, BorshDeserialize, Serialize, Deserialize)]
 #[serde(crate = "near_sdk::serde")]
 pub struct DeployedSmartContract {
     pub contract_deploy_address: AccountId,
+    pub creator_id: AccountId,
     pub frontend_address: String,
     pub contract_name: String,
+    // Set when deployed from a template, the version follows `upgrade_child`
+    pub template_id: Option<String>,
+    pub template_version: Option<String>,
+    pub created_at: U64,
+    pub status: DeploymentStatus,
+    pub description: Option<String>,
+    pub icon: Option<String>,
+    pub tags: Vec<String>,
+}
+
+#[derive(BorshSerialize, BorshDeserialize, Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub enum DeploymentStatus {
+    Active,
+    Deleted,
 }
 
 #[near_bindgen]
@@ -66,13 +127,68 @@ pub struct DeployedSmartContract {
 pub struct Contract {
     pub owner_id: AccountId,
     // Uses:
-    pub creates: LookupMap<AccountId, UnorderedSet<DeployedSmartContract>>,
+    // Creator to the addresses of its deployments, in address order
+    pub creates: LookupMap<AccountId, TreeMap<AccountId, ()>>,
     pub uses: UnorderedMap<ContractAndTokenId, Uses>,
     // Sales
     pub sales: UnorderedMap<ContractAndTokenId, Sale>,
     pub by_owner_id: LookupMap<AccountId, UnorderedSet<ContractAndTokenId>>,
     pub by_contract_id: LookupMap<NFTContractId, UnorderedSet<TokenId>>,
     pub storage_deposit: LookupMap<AccountId, Balance>,
+    // Bundles
+    pub bundles: UnorderedMap<u64, Bundle>,
+    pub bundled_tokens: LookupMap<ContractAndTokenId, u64>,
+    pub next_bundle_id: u64,
+    // Swaps
+    pub swaps: UnorderedMap<u64, Swap>,
+    pub next_swap_id: u64,
+    // Collections
+    pub curators: UnorderedSet<AccountId>,
+    pub listing_policy: ListingPolicy,
+    pub collections: TreeMap<NFTContractId, Collection>,
+    pub transfer_standards: LookupMap<NFTContractId, TransferStandard>,
+    // Moderation
+    pub admins: UnorderedSet<AccountId>,
+    pub banned_accounts: TreeMap<AccountId, ()>,
+    pub reports: UnorderedMap<u64, Report>,
+    pub next_report_id: u64,
+    // Drops
+    pub drops: UnorderedMap<u64, Drop>,
+    pub next_drop_id: u64,
+    pub by_drop_id: LookupMap<u64, TreeMap<ContractAndTokenId, ()>>,
+    pub drop_purchases: LookupMap<String, u32>,
+    // Primary sales
+    pub campaigns: UnorderedMap<u64, Campaign>,
+    pub next_campaign_id: u64,
+    // Factory templates
+    pub templates: TreeMap<String, Template>,
+    pub template_code: LookupMap<CryptoHash, Vec<u8>>,
+    // Deployments by contract address, in address order
+    pub deployments: TreeMap<AccountId, DeployedSmartContract>,
+    pub deployments_by_template_id: LookupMap<String, TreeMap<AccountId, ()>>,
+    // Treasury
+    pub treasury_balance: Balance,
+    pub treasury_ft_balances: UnorderedMap<AccountId, Balance>,
+    pub fee_deposits: LookupMap<(AccountId, AccountId), Balance>,
+    // Search indexes
+    pub sales_by_price: TreeMap<(u128, ContractAndTokenId), ()>,
+    pub uses_by_price: TreeMap<(u128, ContractAndTokenId), ()>,
+    // Listing order of sales and uses, a number per listing that is kept while the owner
+    // relists and leaves a gap once the listing is removed
+    pub sales_by_seq: LookupMap<u64, ContractAndTokenId>,
+    pub sale_seqs: LookupMap<ContractAndTokenId, u64>,
+    pub next_sale_seq: u64,
+    pub uses_by_seq: LookupMap<u64, ContractAndTokenId>,
+    pub use_seqs: LookupMap<ContractAndTokenId, u64>,
+    pub next_use_seq: u64,
+    // Sales per owner and per NFT contract in key order, for cursor pagination
+    pub sales_by_owner: LookupMap<AccountId, TreeMap<ContractAndTokenId, ()>>,
+    pub sales_by_contract: LookupMap<NFTContractId, TreeMap<TokenId, ()>>,
+    // V1 deployment records per creator, moved to `creates` once the creator is migrated
+    pub legacy_creates: LookupMap<AccountId, UnorderedSet<DeployedSmartContractV1>>,
+    // Leading entries of `sales` and `uses` not migrated by `migrate_listings` yet
+    pub sales_to_migrate: u64,
+    pub uses_to_migrate: u64,
 }
 
 #[derive(BorshSerialize, BorshDeserialize)]
@@ -86,20 +202,99 @@ pub enum StorageKey {
     ByContractIdKey,
     InnerByContractIdKey { account_id_hash: CryptoHash },
     StorageDepositKey,
+    CuratorsKey,
+    CollectionsKey,
+    AdminsKey,
+    BannedAccountsKey,
+    ReportsKey,
+    TransferStandardsKey,
+    BundlesKey,
+    BundledTokensKey,
+    SwapsKey,
+    DropsKey,
+    ByDropIdKey,
+    InnerByDropIdKey { drop_id: u64 },
+    DropPurchasesKey,
+    CampaignsKey,
+    TemplatesKey,
+    TemplateCodeKey,
+    CreatesByAddressKey,
+    InnerCreatesByAddressKey { account_id_hash: CryptoHash },
+    DeploymentsKey,
+    DeploymentsByTemplateIdKey,
+    InnerDeploymentsByTemplateIdKey { template_id_hash: CryptoHash },
+    TreasuryFtBalancesKey,
+    FeeDepositsKey,
+    SalesByPriceKey,
+    UsesByPriceKey,
+    SalesBySeqKey,
+    SaleSeqsKey,
+    UsesBySeqKey,
+    UseSeqsKey,
+    SalesByOwnerKey,
+    InnerSalesByOwnerKey { account_id_hash: CryptoHash },
+    SalesByContractKey,
+    InnerSalesByContractKey { account_id_hash: CryptoHash },
 }
 
 #[near_bindgen]
 impl Contract {
     #[init]
     pub fn new(owner_id: AccountId) -> Self {
+        write_state_version(STATE_VERSION);
         Self {
             owner_id,
             sales: UnorderedMap::new(StorageKey::SaleKey.try_to_vec().unwrap()),
             uses: UnorderedMap::new(StorageKey::UsesKey.try_to_vec().unwrap()),
-            creates: LookupMap::new(StorageKey::CreateKey.try_to_vec().unwrap()),
+            creates: LookupMap::new(StorageKey::CreatesByAddressKey.try_to_vec().unwrap()),
             by_owner_id: LookupMap::new(StorageKey::ByOwnerIdKey.try_to_vec().unwrap()),
             by_contract_id: LookupMap::new(StorageKey::ByContractIdKey.try_to_vec().unwrap()),
             storage_deposit: LookupMap::new(StorageKey::StorageDepositKey.try_to_vec().unwrap()),
+            bundles: UnorderedMap::new(StorageKey::BundlesKey.try_to_vec().unwrap()),
+            bundled_tokens: LookupMap::new(StorageKey::BundledTokensKey.try_to_vec().unwrap()),
+            next_bundle_id: 0,
+            swaps: UnorderedMap::new(StorageKey::SwapsKey.try_to_vec().unwrap()),
+            next_swap_id: 0,
+            curators: UnorderedSet::new(StorageKey::CuratorsKey.try_to_vec().unwrap()),
+            listing_policy: ListingPolicy::Open,
+            collections: TreeMap::new(StorageKey::CollectionsKey.try_to_vec().unwrap()),
+            transfer_standards: LookupMap::new(
+                StorageKey::TransferStandardsKey.try_to_vec().unwrap(),
+            ),
+            admins: UnorderedSet::new(StorageKey::AdminsKey.try_to_vec().unwrap()),
+            banned_accounts: TreeMap::new(StorageKey::BannedAccountsKey.try_to_vec().unwrap()),
+            reports: UnorderedMap::new(StorageKey::ReportsKey.try_to_vec().unwrap()),
+            next_report_id: 0,
+            drops: UnorderedMap::new(StorageKey::DropsKey.try_to_vec().unwrap()),
+            next_drop_id: 0,
+            by_drop_id: LookupMap::new(StorageKey::ByDropIdKey.try_to_vec().unwrap()),
+            drop_purchases: LookupMap::new(StorageKey::DropPurchasesKey.try_to_vec().unwrap()),
+            campaigns: UnorderedMap::new(StorageKey::CampaignsKey.try_to_vec().unwrap()),
+            next_campaign_id: 0,
+            templates: TreeMap::new(StorageKey::TemplatesKey.try_to_vec().unwrap()),
+            template_code: LookupMap::new(StorageKey::TemplateCodeKey.try_to_vec().unwrap()),
+            deployments: TreeMap::new(StorageKey::DeploymentsKey.try_to_vec().unwrap()),
+            deployments_by_template_id: LookupMap::new(
+                StorageKey::DeploymentsByTemplateIdKey.try_to_vec().unwrap(),
+            ),
+            treasury_balance: 0,
+            treasury_ft_balances: UnorderedMap::new(
+                StorageKey::TreasuryFtBalancesKey.try_to_vec().unwrap(),
+            ),
+            fee_deposits: LookupMap::new(StorageKey::FeeDepositsKey.try_to_vec().unwrap()),
+            sales_by_price: TreeMap::new(StorageKey::SalesByPriceKey.try_to_vec().unwrap()),
+            uses_by_price: TreeMap::new(StorageKey::UsesByPriceKey.try_to_vec().unwrap()),
+            sales_by_seq: LookupMap::new(StorageKey::SalesBySeqKey.try_to_vec().unwrap()),
+            sale_seqs: LookupMap::new(StorageKey::SaleSeqsKey.try_to_vec().unwrap()),
+            next_sale_seq: 0,
+            uses_by_seq: LookupMap::new(StorageKey::UsesBySeqKey.try_to_vec().unwrap()),
+            use_seqs: LookupMap::new(StorageKey::UseSeqsKey.try_to_vec().unwrap()),
+            next_use_seq: 0,
+            sales_by_owner: LookupMap::new(StorageKey::SalesByOwnerKey.try_to_vec().unwrap()),
+            sales_by_contract: LookupMap::new(StorageKey::SalesByContractKey.try_to_vec().unwrap()),
+            legacy_creates: LookupMap::new(StorageKey::CreateKey.try_to_vec().unwrap()),
+            sales_to_migrate: 0,
+            uses_to_migrate: 0,
         }
     }
 
@@ -0,0 +1,59 @@
+use crate::*;
+
+/// Everything the market holds for one token. The market has no rentals, offers or auctions,
+/// so a listing is its sale, its uses and the bundle it is sold in.
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct Listing {
+    pub nft_contract_id: NFTContractId,
+    pub token_id: TokenId,
+    pub sale: Option<Sale>,
+    pub uses: Option<Uses>,
+    pub bundle: Option<Bundle>,
+}
+
+#[near_bindgen]
+impl Contract {
+    pub fn get_listing(&self, nft_contract_id: NFTContractId, token_id: TokenId) -> Listing {
+        let contract_and_token_id = format!("{}{}{}", nft_contract_id, DELIMETER, token_id);
+        Listing {
+            sale: self.sales.get(&contract_and_token_id),
+            uses: self.uses.get(&contract_and_token_id),
+            bundle: self
+                .bundled_tokens
+                .get(&contract_and_token_id)
+                .and_then(|bundle_id| self.bundles.get(&bundle_id)),
+            nft_contract_id,
+            token_id,
+        }
+    }
+
+    /// Listings of up to `MAX_PAGE_LIMIT` tokens, in the order given.
+    pub fn get_listings(&self, tokens: Vec<(NFTContractId, TokenId)>) -> Vec<Listing> {
+        assert!(
+            tokens.len() as u64 <= MAX_PAGE_LIMIT,
+            "Can look up at most {} tokens",
+            MAX_PAGE_LIMIT
+        );
+        tokens
+            .into_iter()
+            .map(|(nft_contract_id, token_id)| self.get_listing(nft_contract_id, token_id))
+            .collect()
+    }
+}
+
+impl Contract {
+    /// `get_listing` without the sale when it is not live for `viewer_id`.
+    pub(crate) fn listing_for(
+        &self,
+        nft_contract_id: NFTContractId,
+        token_id: TokenId,
+        viewer_id: Option<&AccountId>,
+    ) -> Listing {
+        let mut listing = self.get_listing(nft_contract_id, token_id);
+        listing.sale = listing
+            .sale
+            .filter(|sale| self.is_live_for(sale, viewer_id));
+        listing
+    }
+}
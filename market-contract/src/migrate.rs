@@ -0,0 +1,636 @@
+use crate::*;
+
+/// Layout version of the state currently written by this code.
+pub const STATE_VERSION: u16 = 2;
+const STATE_VERSION_KEY: &[u8] = b"STATE_VERSION";
+const GAS_FOR_UPGRADE: Gas = 10_000_000_000_000;
+// Least `migrate` runs with, it only reads and writes the contract struct
+const MIN_GAS_FOR_MIGRATE: Gas = 20_000_000_000_000;
+
+/// Market state as deployed before state versioning existed (no version key).
+#[derive(BorshSerialize, BorshDeserialize)]
+pub struct ContractV1 {
+    pub owner_id: AccountId,
+    pub creates: LookupMap<AccountId, UnorderedSet<DeployedSmartContractV1>>,
+    pub uses: UnorderedMap<ContractAndTokenId, UsesV1>,
+    pub sales: UnorderedMap<ContractAndTokenId, SaleV1>,
+    pub by_owner_id: LookupMap<AccountId, UnorderedSet<ContractAndTokenId>>,
+    pub by_contract_id: LookupMap<NFTContractId, UnorderedSet<TokenId>>,
+    pub storage_deposit: LookupMap<AccountId, Balance>,
+}
+
+#[derive(BorshSerialize, BorshDeserialize)]
+pub struct DeployedSmartContractV1 {
+    pub contract_deploy_address: AccountId,
+    pub frontend_address: String,
+    pub contract_name: String,
+}
+
+#[derive(BorshSerialize, BorshDeserialize)]
+pub struct SaleV1 {
+    pub owner_id: AccountId,
+    pub approval_id: u64,
+    pub nft_contract_id: NFTContractId,
+    pub token_id: TokenId,
+    pub sale_conditions: SalePriceInYoctoNear,
+}
+
+#[derive(BorshSerialize, BorshDeserialize)]
+pub struct UsesV1 {
+    pub owner_id: AccountId,
+    pub nft_contract_id: NFTContractId,
+    pub token_id: TokenId,
+    pub use_conditions: UsePriceInYoctoNear,
+}
+
+impl DeployedSmartContractV1 {
+    fn into_deployment(self, creator_id: &AccountId) -> DeployedSmartContract {
+        DeployedSmartContract {
+            contract_deploy_address: self.contract_deploy_address,
+            creator_id: creator_id.clone(),
+            frontend_address: self.frontend_address,
+            contract_name: self.contract_name,
+            template_id: None,
+            template_version: None,
+            created_at: U64(0),
+            status: DeploymentStatus::Active,
+            description: None,
+            icon: None,
+            tags: vec![],
+        }
+    }
+}
+
+impl From<SaleV1> for Sale {
+    fn from(sale: SaleV1) -> Self {
+        Sale {
+            owner_id: sale.owner_id,
+            approval_id: sale.approval_id,
+            nft_contract_id: sale.nft_contract_id,
+            token_id: sale.token_id,
+            sale_conditions: sale.sale_conditions,
+            reserved_for: vec![],
+            drop_id: None,
+            listed_at: U64(0),
+        }
+    }
+}
+
+impl From<UsesV1> for Uses {
+    fn from(uses: UsesV1) -> Self {
+        Uses {
+            owner_id: uses.owner_id,
+            nft_contract_id: uses.nft_contract_id,
+            token_id: uses.token_id,
+            use_conditions: uses.use_conditions,
+            listed_at: U64(0),
+        }
+    }
+}
+
+/**
+A V1 sale is the current layout without its trailing fields. `migrate` keeps V1 sales and uses
+in place, they are read with the fields' defaults until `migrate_listings` rewrites them.
+ */
+impl BorshDeserialize for Sale {
+    fn deserialize(buf: &mut &[u8]) -> std::io::Result<Self> {
+        let sale: Sale = SaleV1::deserialize(buf)?.into();
+        if buf.is_empty() {
+            return Ok(sale);
+        }
+        Ok(Sale {
+            reserved_for: BorshDeserialize::deserialize(buf)?,
+            drop_id: BorshDeserialize::deserialize(buf)?,
+            listed_at: BorshDeserialize::deserialize(buf)?,
+            ..sale
+        })
+    }
+}
+
+impl BorshDeserialize for Uses {
+    fn deserialize(buf: &mut &[u8]) -> std::io::Result<Self> {
+        let uses: Uses = UsesV1::deserialize(buf)?.into();
+        if buf.is_empty() {
+            return Ok(uses);
+        }
+        Ok(Uses {
+            listed_at: BorshDeserialize::deserialize(buf)?,
+            ..uses
+        })
+    }
+}
+
+pub(crate) fn read_state_version() -> u16 {
+    env::storage_read(STATE_VERSION_KEY)
+        .map(|value| u16::try_from_slice(&value).expect("Invalid state version"))
+        .unwrap_or(1)
+}
+
+pub(crate) fn write_state_version(version: u16) {
+    env::storage_write(STATE_VERSION_KEY, &version.try_to_vec().unwrap());
+}
+
+#[near_bindgen]
+impl Contract {
+    /**
+    Deploys the wasm passed as raw transaction input to this account and calls `migrate`
+    in the same batch, so a failed migration also reverts the code.
+     */
+    pub fn upgrade(&self) -> Promise {
+        self.assert_owner();
+        let code = env::input().expect("Require new contract code as input");
+        let gas_needed = env::used_gas() + GAS_FOR_UPGRADE + MIN_GAS_FOR_MIGRATE;
+        assert!(
+            env::prepaid_gas() >= gas_needed,
+            "Require at least {} prepaid gas",
+            gas_needed
+        );
+        let migrate_gas = env::prepaid_gas() - env::used_gas() - GAS_FOR_UPGRADE;
+        Promise::new(env::current_account_id())
+            .deploy_contract(code)
+            .function_call(b"migrate".to_vec(), vec![], NO_DEPOSIT, migrate_gas)
+    }
+
+    #[private]
+    #[init(ignore_state)]
+    pub fn migrate() -> Self {
+        let contract = match read_state_version() {
+            1 => Self::migrate_from_v1(env::state_read().expect("Failed to read V1 state")),
+            STATE_VERSION => env::state_read().expect("Failed to read state"),
+            version => env::panic(format!("Unsupported state version {}", version).as_bytes()),
+        };
+        write_state_version(STATE_VERSION);
+        contract
+    }
+
+    pub fn get_state_version(&self) -> u16 {
+        read_state_version()
+    }
+
+    /**
+    Moves the V1 deployment records of `creator_ids` to the current indexes, they are also
+    moved on the creator's next deployment change. Returns how many records were moved.
+     */
+    pub fn migrate_creates(&mut self, creator_ids: Vec<AccountId>) -> U64 {
+        self.assert_owner();
+        let migrated = creator_ids
+            .iter()
+            .map(|creator_id| self.internal_migrate_creates(creator_id))
+            .sum();
+        U64(migrated)
+    }
+
+    /**
+    Migrates up to `limit` of the listings the V1 state left unindexed, call again while
+    listings are left. Until then they can be bought and removed, but views and search may
+    miss them. Returns how many listings are left.
+     */
+    pub fn migrate_listings(&mut self, limit: U64) -> U64 {
+        self.assert_owner();
+        self.internal_migrate_listings(limit.0);
+        self.get_listings_to_migrate()
+    }
+
+    pub fn get_listings_to_migrate(&self) -> U64 {
+        U64(self.sales_to_migrate.min(self.sales.len()) + self.uses_to_migrate.min(self.uses.len()))
+    }
+}
+
+/// Reopens `map` with the value type its values are read as now.
+fn reopen<K, V, W>(map: UnorderedMap<K, V>) -> UnorderedMap<K, W>
+where
+    K: BorshSerialize + BorshDeserialize,
+    V: BorshSerialize + BorshDeserialize,
+    W: BorshSerialize + BorshDeserialize,
+{
+    UnorderedMap::try_from_slice(&map.try_to_vec().unwrap()).unwrap()
+}
+
+impl Contract {
+    /// Moves the V1 deployment records of `creator_id` to `creates` and `deployments`.
+    pub(crate) fn internal_migrate_creates(&mut self, creator_id: &AccountId) -> u64 {
+        let mut legacy_creates =
+            if let Some(legacy_creates) = self.legacy_creates.remove(creator_id) {
+                legacy_creates
+            } else {
+                return 0;
+            };
+        let mut migrated = 0;
+        for legacy in legacy_creates.to_vec() {
+            if self
+                .deployments
+                .get(&legacy.contract_deploy_address)
+                .is_none()
+            {
+                self.internal_add_deployment(legacy.into_deployment(creator_id));
+                migrated += 1;
+            }
+        }
+        legacy_creates.clear();
+        migrated
+    }
+
+    /// V1 deployment records of `creator_id` not migrated yet.
+    pub(crate) fn legacy_deployments(&self, creator_id: &AccountId) -> Vec<DeployedSmartContract> {
+        self.legacy_creates
+            .get(creator_id)
+            .map(|legacy_creates| {
+                legacy_creates
+                    .iter()
+                    .map(|legacy| legacy.into_deployment(creator_id))
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    fn migrate_from_v1(old: ContractV1) -> Self {
+        let mut contract = Self {
+            owner_id: old.owner_id,
+            // V1 records sit in sets per creator that can not be enumerated, they stay under
+            // the old prefix in `legacy_creates` until `internal_migrate_creates` moves them
+            creates: LookupMap::new(StorageKey::CreatesByAddressKey.try_to_vec().unwrap()),
+            uses: reopen(old.uses),
+            sales: reopen(old.sales),
+            by_owner_id: old.by_owner_id,
+            by_contract_id: old.by_contract_id,
+            storage_deposit: old.storage_deposit,
+            bundles: UnorderedMap::new(StorageKey::BundlesKey.try_to_vec().unwrap()),
+            bundled_tokens: LookupMap::new(StorageKey::BundledTokensKey.try_to_vec().unwrap()),
+            next_bundle_id: 0,
+            swaps: UnorderedMap::new(StorageKey::SwapsKey.try_to_vec().unwrap()),
+            next_swap_id: 0,
+            curators: UnorderedSet::new(StorageKey::CuratorsKey.try_to_vec().unwrap()),
+            listing_policy: ListingPolicy::Open,
+            collections: TreeMap::new(StorageKey::CollectionsKey.try_to_vec().unwrap()),
+            transfer_standards: LookupMap::new(
+                StorageKey::TransferStandardsKey.try_to_vec().unwrap(),
+            ),
+            admins: UnorderedSet::new(StorageKey::AdminsKey.try_to_vec().unwrap()),
+            banned_accounts: TreeMap::new(StorageKey::BannedAccountsKey.try_to_vec().unwrap()),
+            reports: UnorderedMap::new(StorageKey::ReportsKey.try_to_vec().unwrap()),
+            next_report_id: 0,
+            drops: UnorderedMap::new(StorageKey::DropsKey.try_to_vec().unwrap()),
+            next_drop_id: 0,
+            by_drop_id: LookupMap::new(StorageKey::ByDropIdKey.try_to_vec().unwrap()),
+            drop_purchases: LookupMap::new(StorageKey::DropPurchasesKey.try_to_vec().unwrap()),
+            campaigns: UnorderedMap::new(StorageKey::CampaignsKey.try_to_vec().unwrap()),
+            next_campaign_id: 0,
+            templates: TreeMap::new(StorageKey::TemplatesKey.try_to_vec().unwrap()),
+            template_code: LookupMap::new(StorageKey::TemplateCodeKey.try_to_vec().unwrap()),
+            deployments: TreeMap::new(StorageKey::DeploymentsKey.try_to_vec().unwrap()),
+            deployments_by_template_id: LookupMap::new(
+                StorageKey::DeploymentsByTemplateIdKey.try_to_vec().unwrap(),
+            ),
+            treasury_balance: 0,
+            treasury_ft_balances: UnorderedMap::new(
+                StorageKey::TreasuryFtBalancesKey.try_to_vec().unwrap(),
+            ),
+            fee_deposits: LookupMap::new(StorageKey::FeeDepositsKey.try_to_vec().unwrap()),
+            sales_by_price: TreeMap::new(StorageKey::SalesByPriceKey.try_to_vec().unwrap()),
+            uses_by_price: TreeMap::new(StorageKey::UsesByPriceKey.try_to_vec().unwrap()),
+            sales_by_seq: LookupMap::new(StorageKey::SalesBySeqKey.try_to_vec().unwrap()),
+            sale_seqs: LookupMap::new(StorageKey::SaleSeqsKey.try_to_vec().unwrap()),
+            next_sale_seq: 0,
+            uses_by_seq: LookupMap::new(StorageKey::UsesBySeqKey.try_to_vec().unwrap()),
+            use_seqs: LookupMap::new(StorageKey::UseSeqsKey.try_to_vec().unwrap()),
+            next_use_seq: 0,
+            sales_by_owner: LookupMap::new(StorageKey::SalesByOwnerKey.try_to_vec().unwrap()),
+            sales_by_contract: LookupMap::new(StorageKey::SalesByContractKey.try_to_vec().unwrap()),
+            legacy_creates: old.creates,
+            sales_to_migrate: 0,
+            uses_to_migrate: 0,
+        };
+        // Rewriting and indexing every listing does not fit in one call, `migrate_listings`
+        // does it in batches
+        contract.sales_to_migrate = contract.sales.len();
+        contract.uses_to_migrate = contract.uses.len();
+        contract
+    }
+
+    /**
+    Rewrites up to `limit` of the sales and uses left to migrate in the current layout and adds
+    them to the listing indexes, from the end of `sales` and `uses` towards the start. Removing
+    a listing swaps the last one into its place, so the unmigrated listings always stay at the
+    start and none is skipped.
+     */
+    fn internal_migrate_listings(&mut self, limit: u64) -> u64 {
+        let mut migrated = 0;
+        self.sales_to_migrate = self.sales_to_migrate.min(self.sales.len());
+        while self.sales_to_migrate > 0 && migrated < limit {
+            self.sales_to_migrate -= 1;
+            let sale = self
+                .sales
+                .values_as_vector()
+                .get(self.sales_to_migrate)
+                .unwrap();
+            self.internal_add_sale(&sale);
+            migrated += 1;
+        }
+        self.uses_to_migrate = self.uses_to_migrate.min(self.uses.len());
+        while self.uses_to_migrate > 0 && migrated < limit {
+            self.uses_to_migrate -= 1;
+            let uses = self
+                .uses
+                .values_as_vector()
+                .get(self.uses_to_migrate)
+                .unwrap();
+            self.internal_add_uses(&uses);
+            migrated += 1;
+        }
+        migrated
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use near_sdk::test_utils::{accounts, VMContextBuilder};
+    use near_sdk::{testing_env, MockedBlockchain, RuntimeFeesConfig, VMConfig};
+
+    const MAX_PREPAID_GAS: Gas = 300_000_000_000_000;
+    // Listings a `migrate_listings` call is tested with
+    const LISTINGS_PER_CALL: u64 = 10;
+
+    fn contract_v1(owner_id: AccountId) -> ContractV1 {
+        ContractV1 {
+            owner_id,
+            creates: LookupMap::new(StorageKey::CreateKey.try_to_vec().unwrap()),
+            uses: UnorderedMap::new(StorageKey::UsesKey.try_to_vec().unwrap()),
+            sales: UnorderedMap::new(StorageKey::SaleKey.try_to_vec().unwrap()),
+            by_owner_id: LookupMap::new(StorageKey::ByOwnerIdKey.try_to_vec().unwrap()),
+            by_contract_id: LookupMap::new(StorageKey::ByContractIdKey.try_to_vec().unwrap()),
+            storage_deposit: LookupMap::new(StorageKey::StorageDepositKey.try_to_vec().unwrap()),
+        }
+    }
+
+    fn sale_v1(seller_id: &AccountId, nft_contract_id: &AccountId, token_id: u64) -> SaleV1 {
+        SaleV1 {
+            owner_id: seller_id.clone(),
+            approval_id: token_id,
+            nft_contract_id: nft_contract_id.clone(),
+            token_id: token_id.to_string(),
+            sale_conditions: U128(100 + u128::from(token_id)),
+        }
+    }
+
+    fn uses_v1(seller_id: &AccountId, nft_contract_id: &AccountId, token_id: u64) -> UsesV1 {
+        UsesV1 {
+            owner_id: seller_id.clone(),
+            nft_contract_id: nft_contract_id.clone(),
+            token_id: token_id.to_string(),
+            use_conditions: U128(10),
+        }
+    }
+
+    #[test]
+    fn migrate_from_v1_keeps_listings() {
+        testing_env!(VMContextBuilder::new()
+            .predecessor_account_id(accounts(0))
+            .build());
+        let owner_id: AccountId = accounts(0).into();
+        let seller_id: AccountId = accounts(1).into();
+        let nft_contract_id: AccountId = accounts(2).into();
+        let contract_and_token_id = format!("{}{}{}", nft_contract_id, DELIMETER, "1");
+
+        let mut old = contract_v1(owner_id.clone());
+        old.sales.insert(
+            &contract_and_token_id,
+            &SaleV1 {
+                owner_id: seller_id.clone(),
+                approval_id: 7,
+                nft_contract_id: nft_contract_id.clone(),
+                token_id: "1".to_string(),
+                sale_conditions: U128(100),
+            },
+        );
+        old.uses.insert(
+            &contract_and_token_id,
+            &UsesV1 {
+                owner_id: seller_id.clone(),
+                nft_contract_id: nft_contract_id.clone(),
+                token_id: "1".to_string(),
+                use_conditions: U128(10),
+            },
+        );
+        old.storage_deposit.insert(&seller_id, &STORAGE_PER_SALE);
+        env::state_write(&old);
+
+        let mut contract = Contract::migrate();
+        assert_eq!(read_state_version(), STATE_VERSION);
+        assert_eq!(contract.owner_id, owner_id);
+
+        let sale = contract.sales.get(&contract_and_token_id).unwrap();
+        assert_eq!(sale.owner_id, seller_id);
+        assert_eq!(sale.approval_id, 7);
+        assert_eq!(sale.sale_conditions.0, 100);
+        let uses = contract.uses.get(&contract_and_token_id).unwrap();
+        assert_eq!(uses.use_conditions.0, 10);
+        assert_eq!(contract.storage_balance_of(seller_id).0, STORAGE_PER_SALE);
+        assert_eq!(contract.get_listings_to_migrate().0, 2);
+        assert_eq!(contract.migrate_listings(U64(10)).0, 0);
+        let sale = contract.sales.get(&contract_and_token_id).unwrap();
+        assert_eq!(sale.approval_id, 7);
+        assert!(sale.reserved_for.is_empty());
+        assert_eq!(contract.get_sales(None, None, None).items.len(), 1);
+        assert_eq!(contract.get_uses(None, None).items.len(), 1);
+    }
+
+    /// Fresh call of the owner with the most gas a transaction can attach.
+    fn owner_call() {
+        let mut config = VMConfig::default();
+        config.limit_config.max_gas_burnt = MAX_PREPAID_GAS;
+        testing_env!(
+            VMContextBuilder::new()
+                .predecessor_account_id(accounts(0))
+                .prepaid_gas(MAX_PREPAID_GAS)
+                .build(),
+            config,
+            RuntimeFeesConfig::default()
+        );
+    }
+
+    #[test]
+    fn migrate_listings_in_batches() {
+        let mut config = VMConfig::default();
+        config.limit_config.max_gas_burnt = u64::MAX;
+        testing_env!(
+            VMContextBuilder::new().prepaid_gas(u64::MAX).build(),
+            config,
+            RuntimeFeesConfig::default()
+        );
+        let seller_id: AccountId = accounts(1).into();
+        let nft_contract_id: AccountId = accounts(2).into();
+        let listings = 120;
+
+        let mut old = contract_v1(accounts(0).into());
+        let mut by_owner_id = UnorderedSet::new(
+            StorageKey::InnerByOwnerIdKey {
+                account_id_hash: hash_account_id(&seller_id),
+            }
+            .try_to_vec()
+            .unwrap(),
+        );
+        let mut by_contract_id = UnorderedSet::new(
+            StorageKey::InnerByContractIdKey {
+                account_id_hash: hash_account_id(&nft_contract_id),
+            }
+            .try_to_vec()
+            .unwrap(),
+        );
+        for token_id in 0..listings {
+            let contract_and_token_id = format!("{}{}{}", nft_contract_id, DELIMETER, token_id);
+            old.sales.insert(
+                &contract_and_token_id,
+                &sale_v1(&seller_id, &nft_contract_id, token_id),
+            );
+            old.uses.insert(
+                &contract_and_token_id,
+                &uses_v1(&seller_id, &nft_contract_id, token_id),
+            );
+            by_owner_id.insert(&contract_and_token_id);
+            by_contract_id.insert(&token_id.to_string());
+        }
+        old.by_owner_id.insert(&seller_id, &by_owner_id);
+        old.by_contract_id.insert(&nft_contract_id, &by_contract_id);
+        env::state_write(&old);
+
+        owner_call();
+        let mut contract = Contract::migrate();
+        assert_eq!(contract.get_listings_to_migrate().0, 2 * listings);
+        owner_call();
+        assert_eq!(
+            contract.migrate_listings(U64(LISTINGS_PER_CALL)).0,
+            2 * listings - LISTINGS_PER_CALL
+        );
+
+        // Listings can be removed while the migration is under way, migrated or not
+        for token_id in [0, 60, 119] {
+            owner_call();
+            contract.internal_remove_listing(nft_contract_id.clone(), token_id.to_string());
+        }
+        let mut left = contract.get_listings_to_migrate().0;
+        while left > 0 {
+            owner_call();
+            let next = contract.migrate_listings(U64(LISTINGS_PER_CALL)).0;
+            assert!(next < left);
+            left = next;
+        }
+
+        let mut sales = 0;
+        let mut from_key = None;
+        loop {
+            let page = contract.get_sales(from_key, Some(U64(100)), None);
+            sales += page.items.len();
+            if page.next_key.is_none() {
+                break;
+            }
+            from_key = page.next_key;
+        }
+        assert_eq!(sales as u64, listings - 3);
+        let page = contract.get_sale_by_owner_id(seller_id, None, Some(U64(100)), None);
+        assert_eq!(page.items.len(), 100);
+        let page = contract.get_sales_by_cotnract_id(nft_contract_id, None, Some(U64(100)), None);
+        assert_eq!(page.items.len(), 100);
+        assert_eq!(contract.sales_by_price.len(), listings - 3);
+        assert_eq!(contract.uses_by_price.len(), listings - 3);
+    }
+
+    #[test]
+    fn migrate_from_v1_keeps_creates() {
+        testing_env!(VMContextBuilder::new()
+            .predecessor_account_id(accounts(0))
+            .build());
+        let owner_id: AccountId = accounts(0).into();
+        let creator_id: AccountId = accounts(1).into();
+        let contract_deploy_address: AccountId = accounts(2).into();
+
+        let mut old = contract_v1(owner_id);
+        let mut creates = UnorderedSet::new(
+            StorageKey::InnerByCreatorIdKey {
+                account_id_hash: hash_account_id(&creator_id),
+            }
+            .try_to_vec()
+            .unwrap(),
+        );
+        creates.insert(&DeployedSmartContractV1 {
+            contract_deploy_address: contract_deploy_address.clone(),
+            frontend_address: "https://example.com".to_string(),
+            contract_name: "nft".to_string(),
+        });
+        old.creates.insert(&creator_id, &creates);
+        env::state_write(&old);
+
+        let mut contract = Contract::migrate();
+        assert_eq!(
+            contract
+                .get_supply_deployments_by_creator(creator_id.clone())
+                .0,
+            1
+        );
+        let deployments = contract
+            .get_created_contract_by_creator(creator_id.clone(), None, None)
+            .items;
+        assert_eq!(deployments.len(), 1);
+        assert_eq!(
+            deployments[0].contract_deploy_address,
+            contract_deploy_address
+        );
+        assert_eq!(deployments[0].creator_id, creator_id);
+        assert!(contract
+            .get_deployment(contract_deploy_address.clone())
+            .is_none());
+
+        assert_eq!(contract.migrate_creates(vec![creator_id.clone()]).0, 1);
+        let deployment = contract
+            .get_deployment(contract_deploy_address.clone())
+            .unwrap();
+        assert_eq!(deployment.contract_name, "nft");
+        assert_eq!(
+            contract
+                .get_supply_deployments_by_creator(creator_id.clone())
+                .0,
+            1
+        );
+        assert_eq!(
+            contract
+                .get_created_contract_by_creator(creator_id, None, None)
+                .items
+                .len(),
+            1
+        );
+    }
+
+    #[test]
+    fn migrate_current_version_is_noop() {
+        testing_env!(VMContextBuilder::new().build());
+        let owner_id: AccountId = accounts(0).into();
+        let contract = Contract::new(owner_id.clone());
+        env::state_write(&contract);
+
+        let contract = Contract::migrate();
+        assert_eq!(contract.owner_id, owner_id);
+        assert_eq!(contract.get_state_version(), STATE_VERSION);
+    }
+
+    #[test]
+    #[should_panic(expected = "Require at least")]
+    fn upgrade_requires_gas_for_migrate() {
+        let mut context = VMContextBuilder::new()
+            .predecessor_account_id(accounts(0))
+            .prepaid_gas(GAS_FOR_UPGRADE)
+            .build();
+        context.input = vec![0; 100];
+        testing_env!(context);
+        let contract = Contract::new(accounts(0).into());
+        contract.upgrade();
+    }
+
+    #[test]
+    #[should_panic(expected = "Only contract owner can call this method")]
+    fn upgrade_requires_owner() {
+        let mut context = VMContextBuilder::new();
+        testing_env!(context.predecessor_account_id(accounts(1)).build());
+        let contract = Contract::new(accounts(0).into());
+        contract.upgrade();
+    }
+}
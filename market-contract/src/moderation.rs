@@ -0,0 +1,150 @@
+use crate::*;
+
+const REPORT_BOND: Balance = 100_000_000_000_000_000_000_000; // 1e23yN, 0.1N
+
+#[derive(BorshSerialize, BorshDeserialize, Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub enum ReportStatus {
+    Open,
+    // Bond returned to the reporter and listing removed
+    Upheld,
+    // Bond kept by the market
+    Dismissed,
+}
+
+#[derive(BorshSerialize, BorshDeserialize, Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct Report {
+    pub report_id: u64,
+    pub reporter_id: AccountId,
+    pub seller_id: AccountId,
+    pub nft_contract_id: NFTContractId,
+    pub token_id: TokenId,
+    pub reason: String,
+    pub bond: U128,
+    pub status: ReportStatus,
+    pub created_at: U64,
+}
+
+#[near_bindgen]
+impl Contract {
+    #[payable]
+    pub fn add_admin(&mut self, account_id: AccountId) {
+        assert_one_yocto();
+        self.assert_owner();
+        self.admins.insert(&account_id);
+    }
+
+    #[payable]
+    pub fn remove_admin(&mut self, account_id: AccountId) {
+        assert_one_yocto();
+        self.assert_owner();
+        self.admins.remove(&account_id);
+    }
+
+    #[payable]
+    pub fn ban_account(&mut self, account_id: AccountId) {
+        assert_one_yocto();
+        self.assert_admin();
+        assert_ne!(account_id, self.owner_id, "Can not ban contract owner");
+        self.banned_accounts.insert(&account_id, &());
+    }
+
+    #[payable]
+    pub fn unban_account(&mut self, account_id: AccountId) {
+        assert_one_yocto();
+        self.assert_admin();
+        self.banned_accounts.remove(&account_id);
+    }
+
+    /**
+    Removes up to `limit` listings of a banned seller. Anyone can call it until
+    `get_supply_by_owner_id` of the seller reaches 0.
+     */
+    pub fn delist_banned_seller(&mut self, account_id: AccountId, limit: u64) -> U64 {
+        assert!(
+            self.banned_accounts.contains_key(&account_id),
+            "Account {} is not banned",
+            account_id
+        );
+        let contract_token_ids: Vec<ContractAndTokenId> = match self.by_owner_id.get(&account_id) {
+            Some(by_owner_id) => by_owner_id
+                .as_vector()
+                .iter()
+                .take(limit as usize)
+                .collect(),
+            None => return U64(0),
+        };
+
+        for contract_and_token_id in contract_token_ids.iter() {
+            let sale = self
+                .sales
+                .get(contract_and_token_id)
+                .expect("Not found sale");
+            self.internal_remove_listing(sale.nft_contract_id, sale.token_id);
+        }
+        U64(contract_token_ids.len() as u64)
+    }
+
+    #[payable]
+    pub fn file_report(
+        &mut self,
+        nft_contract_id: AccountId,
+        token_id: TokenId,
+        reason: String,
+    ) -> u64 {
+        let deposit = env::attached_deposit();
+        assert!(
+            deposit >= REPORT_BOND,
+            "Require report bond of {}",
+            REPORT_BOND
+        );
+        let contract_and_token_id = format!("{}{}{}", nft_contract_id, DELIMETER, token_id);
+        let seller_id = if let Some(sale) = self.sales.get(&contract_and_token_id) {
+            sale.owner_id
+        } else {
+            self.uses
+                .get(&contract_and_token_id)
+                .expect("Not found listing")
+                .owner_id
+        };
+
+        let report_id = self.next_report_id;
+        self.next_report_id += 1;
+        self.reports.insert(
+            &report_id,
+            &Report {
+                report_id,
+                reporter_id: env::predecessor_account_id(),
+                seller_id,
+                nft_contract_id,
+                token_id,
+                reason,
+                bond: U128(deposit),
+                status: ReportStatus::Open,
+                created_at: U64(env::block_timestamp()),
+            },
+        );
+        report_id
+    }
+
+    #[payable]
+    pub fn resolve_report(&mut self, report_id: u64, upheld: bool) {
+        assert_one_yocto();
+        self.assert_curator();
+        let mut report = self.reports.get(&report_id).expect("Not found report");
+        assert!(
+            report.status == ReportStatus::Open,
+            "Report is already resolved"
+        );
+
+        if upheld {
+            report.status = ReportStatus::Upheld;
+            self.internal_remove_listing(report.nft_contract_id.clone(), report.token_id.clone());
+            Promise::new(report.reporter_id.clone()).transfer(report.bond.0);
+        } else {
+            report.status = ReportStatus::Dismissed;
+        }
+        self.reports.insert(&report_id, &report);
+    }
+}
@@ -0,0 +1,38 @@
+use crate::*;
+
+#[near_bindgen]
+impl Contract {
+    pub fn is_banned(&self, account_id: AccountId) -> bool {
+        self.banned_accounts.contains_key(&account_id)
+    }
+
+    /// Banned accounts in account order, pass `next_key` of the previous page as `from_key`.
+    pub fn get_banned_accounts(
+        &self,
+        from_key: Option<AccountId>,
+        limit: Option<U64>,
+    ) -> Page<AccountId, AccountId> {
+        filtered_page(
+            index_keys_after(&self.banned_accounts, from_key),
+            limit,
+            |account_id| Some(account_id.clone()),
+        )
+    }
+
+    pub fn get_admins(&self) -> Vec<AccountId> {
+        self.admins.to_vec()
+    }
+
+    pub fn get_report(&self, report_id: u64) -> Option<Report> {
+        self.reports.get(&report_id)
+    }
+
+    /// Reports in id order, pass `next_key` of the previous page as `from_key`.
+    pub fn get_reports(&self, from_key: Option<U64>, limit: Option<U64>) -> Page<Report, U64> {
+        filtered_page(
+            ids_after(from_key, self.next_report_id),
+            limit,
+            |report_id| self.reports.get(&report_id.0),
+        )
+    }
+}
@@ -15,12 +15,32 @@ pub trait NonFungibleTokenApprovalReceiver {
 pub struct ListingArgs {
     pub sale_condition: SalePriceInYoctoNear,
     pub use_condition: UsePriceInYoctoNear,
+    pub reserved_for: Option<Vec<AccountId>>,
+    pub drop_id: Option<u64>,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde", rename_all = "snake_case")]
+pub enum MarketAction {
+    Delist,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde", untagged)]
+pub enum ApprovalMsg {
+    Action { action: MarketAction },
+    Swap { swap_id: u64 },
+    Listing(ListingArgs),
 }
 
 #[near_bindgen]
 impl NonFungibleTokenApprovalReceiver for Contract {
     /**
-    msg: {"sale_condition": "100000000000", "use_conditions:"1000000000"}
+    msg: {"sale_condition": "100000000000", "use_conditions:"1000000000", "reserved_for": ["buyer.testnet"], "drop_id": 0}
+    Approving an already listed token again updates its prices and approval_id. A bundled token
+    can not be reserved or added to a drop this way.
+    msg: {"action": "delist"} removes the listing; the approval itself should be revoked on the NFT contract.
+    msg: {"swap_id": 0} approves the token for a proposed swap.
      */
     fn nft_on_approve(
         &mut self,
@@ -38,11 +58,69 @@ impl NonFungibleTokenApprovalReceiver for Contract {
         );
         assert_eq!(signer_id, owner_id, "owner_id should be signer_id");
 
+        let approval_msg: ApprovalMsg =
+            near_sdk::serde_json::from_str(&msg).expect("Not valid Sale Args");
+        let ListingArgs {
+            sale_condition,
+            use_condition,
+            reserved_for,
+            drop_id,
+        } = match approval_msg {
+            ApprovalMsg::Action {
+                action: MarketAction::Delist,
+            } => {
+                // owner_id is the current token owner, so any other listing of the token is stale
+                self.internal_remove_listing(nft_contract_id, token_id);
+                return;
+            }
+            ApprovalMsg::Swap { swap_id } => {
+                self.assert_not_banned(&owner_id);
+                self.internal_approve_swap_token(
+                    swap_id,
+                    nft_contract_id,
+                    token_id,
+                    owner_id,
+                    approval_id,
+                );
+                return;
+            }
+            ApprovalMsg::Listing(listing_args) => listing_args,
+        };
+
+        self.assert_collection_listable(&nft_contract_id);
+        self.assert_not_banned(&owner_id);
+        if let Some(drop_id) = drop_id {
+            let drop = self.drops.get(&drop_id).expect("Not found drop");
+            assert_eq!(drop.owner_id, owner_id, "Drop belongs to another account");
+        }
+
+        let contract_and_token_id = format!("{}{}{}", nft_contract_id.clone(), DELIMETER, token_id);
+        // Re-approval by the same owner only updates the listing and takes no extra storage.
+        // A listing left by a previous owner is dropped before the new owner lists.
+        let relisted_at = match self.sales.get(&contract_and_token_id) {
+            Some(sale) if sale.owner_id == owner_id => Some(sale.listed_at),
+            Some(_) => {
+                self.internal_remove_sale(nft_contract_id.clone(), token_id.clone());
+                None
+            }
+            None => None,
+        };
+        let is_relisting = relisted_at.is_some();
+        if is_relisting && self.bundled_tokens.contains_key(&contract_and_token_id) {
+            assert!(
+                !matches!(&reserved_for, Some(accounts) if !accounts.is_empty()),
+                "Reserved sales can not be bundled"
+            );
+            assert!(drop_id.is_none(), "Drop sales can not be bundled");
+        }
+        let listed_at = relisted_at.unwrap_or_else(|| U64(env::block_timestamp()));
+
         // Check cover storage
         let storage_balance = self.storage_deposit.get(&signer_id).unwrap_or(0);
         let storage_minimum_amount = self.storage_minimum_balance().0;
+        let new_sales = if is_relisting { 0 } else { 1 };
         let storage_required =
-            (self.get_supply_by_owner_id(signer_id.clone()).0 + 1) * storage_minimum_amount;
+            (self.get_supply_by_owner_id(signer_id.clone()).0 + new_sales) * storage_minimum_amount;
 
         assert!(
             storage_balance >= storage_required,
@@ -52,60 +130,23 @@ impl NonFungibleTokenApprovalReceiver for Contract {
             STORAGE_PER_SALE
         );
 
-        let ListingArgs {
-            sale_condition,
-            use_condition,
-        } = near_sdk::serde_json::from_str(&msg).expect("Not valid Sale Args");
-        let contract_and_token_id = format!("{}{}{}", nft_contract_id.clone(), DELIMETER, token_id);
-
-        self.sales.insert(
-            &contract_and_token_id,
-            &Sale {
-                owner_id: owner_id.clone(),
-                approval_id,
-                nft_contract_id: nft_contract_id.clone(),
-                token_id: token_id.clone(),
-                sale_conditions: sale_condition,
-            },
-        );
-
-        self.uses.insert(
-            &contract_and_token_id,
-            &Uses {
-                owner_id: owner_id.clone(),
-                nft_contract_id: nft_contract_id.clone(),
-                token_id: token_id.clone(),
-                use_conditions: use_condition,
-            },
-        );
-
-        let mut by_owner_id = self.by_owner_id.get(&owner_id).unwrap_or_else(|| {
-            UnorderedSet::new(
-                StorageKey::InnerByOwnerIdKey {
-                    account_id_hash: hash_account_id(&owner_id),
-                }
-                .try_to_vec()
-                .unwrap(),
-            )
+        self.internal_add_uses(&Uses {
+            owner_id: owner_id.clone(),
+            nft_contract_id: nft_contract_id.clone(),
+            token_id: token_id.clone(),
+            use_conditions: use_condition,
+            listed_at,
         });
 
-        by_owner_id.insert(&contract_and_token_id);
-        self.by_owner_id.insert(&owner_id, &by_owner_id);
-
-        let mut by_contract_id = self
-            .by_contract_id
-            .get(&nft_contract_id)
-            .unwrap_or_else(|| {
-                UnorderedSet::new(
-                    StorageKey::InnerByContractIdKey {
-                        account_id_hash: hash_account_id(&nft_contract_id),
-                    }
-                    .try_to_vec()
-                    .unwrap(),
-                )
-            });
-        by_contract_id.insert(&token_id);
-        self.by_contract_id
-            .insert(&nft_contract_id, &by_contract_id);
+        self.internal_add_sale(&Sale {
+            owner_id,
+            approval_id,
+            nft_contract_id,
+            token_id,
+            sale_conditions: sale_condition,
+            reserved_for: reserved_for.unwrap_or_default(),
+            drop_id,
+            listed_at,
+        });
     }
 }
@@ -0,0 +1,78 @@
+use crate::*;
+
+/*
+List views take `from_key`, the key of the last item of the previous page, and `limit`. They
+walk an ordered index or, for records keyed by a sequential id, the ids after `from_key`, so
+removing items never shifts a page the way it does with indexes into unordered collections.
+Every view examines at most `MAX_PAGE_SCAN` keys per call and returns a `Page` whose
+`next_key` is where the next call picks up.
+*/
+
+/// Page size when `limit` is not given.
+pub const DEFAULT_PAGE_LIMIT: u64 = 50;
+/// Largest page a view returns, larger limits are clamped.
+pub const MAX_PAGE_LIMIT: u64 = 100;
+/// Most keys a filtering view examines per call.
+pub const MAX_PAGE_SCAN: u64 = 500;
+
+/// Items of a list view. `next_key` is passed as `from_key` for the next page and is
+/// `None` once the index is exhausted, a short or empty page does not mean the end.
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct Page<T, K> {
+    pub items: Vec<T>,
+    pub next_key: Option<K>,
+}
+
+pub(crate) fn page_limit(limit: Option<U64>) -> usize {
+    limit
+        .map(|limit| limit.0)
+        .unwrap_or(DEFAULT_PAGE_LIMIT)
+        .min(MAX_PAGE_LIMIT) as usize
+}
+
+/// Keys of an ordered index after `from_key`, in ascending order.
+pub(crate) fn index_keys_after<'a, K, V>(
+    index: &'a TreeMap<K, V>,
+    from_key: Option<K>,
+) -> Box<dyn Iterator<Item = K> + 'a>
+where
+    K: Ord + Clone + BorshSerialize + BorshDeserialize,
+    V: BorshSerialize + BorshDeserialize,
+{
+    match from_key {
+        Some(from_key) => Box::new(index.iter_from(from_key).map(|(key, _)| key)),
+        None => Box::new(index.iter().map(|(key, _)| key)),
+    }
+}
+
+/// Ids after `from_key` of records numbered from 0 up to `next_id`, removed ids included.
+pub(crate) fn ids_after(from_key: Option<U64>, next_id: u64) -> impl Iterator<Item = U64> {
+    let start = from_key.map_or(0, |from_key| from_key.0.saturating_add(1));
+    (start..next_id).map(U64)
+}
+
+/// Collects up to `limit` items `item` returns for `keys`, examining at most `MAX_PAGE_SCAN` keys.
+pub(crate) fn filtered_page<K, T>(
+    keys: impl Iterator<Item = K>,
+    limit: Option<U64>,
+    mut item: impl FnMut(&K) -> Option<T>,
+) -> Page<T, K> {
+    let limit = page_limit(limit);
+    let mut items = vec![];
+    for (scanned, key) in keys.enumerate() {
+        if let Some(item) = item(&key) {
+            items.push(item);
+        }
+        if items.len() == limit || scanned + 1 == MAX_PAGE_SCAN as usize {
+            return Page {
+                items,
+                next_key: Some(key),
+            };
+        }
+    }
+    Page {
+        items,
+        next_key: None,
+    }
+}
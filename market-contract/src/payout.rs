@@ -0,0 +1,352 @@
+use std::collections::HashMap;
+
+use crate::*;
+
+pub const DEFAULT_MAX_LEN_PAYOUT: u32 = 10;
+const DEFAULT_ROUNDING_TOLERANCE: Balance = 1;
+const BASIS_POINTS: u128 = 10_000;
+
+/// Payout rules of one NFT contract, resolved from its `Collection` with defaults for the rest.
+pub struct PayoutPolicy {
+    pub max_len_payout: u32,
+    pub rounding_tolerance: Balance,
+    pub royalty_cap_bps: Option<u32>,
+    pub over_cap_policy: OverCapPolicy,
+}
+
+impl PayoutPolicy {
+    pub fn rejects_over_cap(&self) -> bool {
+        self.royalty_cap_bps.is_some() && self.over_cap_policy == OverCapPolicy::Reject
+    }
+
+    /// Parses a `Payout` returned by the NFT contract and checks it splits `price`
+    /// within the rounding tolerance.
+    pub fn checked_payout(&self, value: &[u8], price: U128) -> Option<HashMap<AccountId, U128>> {
+        let payout_object = near_sdk::serde_json::from_slice::<Payout>(value).ok()?;
+        if payout_object.payout.len() > self.max_len_payout as usize
+            || payout_object.payout.is_empty()
+        {
+            env::log(
+                format!("Cannot have more than {} royalities", self.max_len_payout).as_bytes(),
+            );
+            None
+        } else {
+            let mut remainder = price.0;
+            for &value in payout_object.payout.values() {
+                remainder = remainder.checked_sub(value.0)?;
+            }
+
+            if remainder <= self.rounding_tolerance {
+                Some(payout_object.payout)
+            } else {
+                None
+            }
+        }
+    }
+
+    pub fn exceeds_royalty_cap(
+        &self,
+        payout: &HashMap<AccountId, U128>,
+        price: U128,
+        seller_id: &AccountId,
+    ) -> bool {
+        match self.royalty_cap(price) {
+            Some(royalty_cap) => total_royalties(payout, seller_id) > royalty_cap,
+            None => false,
+        }
+    }
+
+    /// Checks the payout and brings its royalties under the cap, the seller receives what is cut
+    /// along with any rounding remainder left within the tolerance.
+    /// `Reject` is enforced before the transfer, so a payout still over the cap here is clamped.
+    pub fn apply(
+        &self,
+        value: &[u8],
+        price: U128,
+        seller_id: &AccountId,
+    ) -> Option<HashMap<AccountId, U128>> {
+        let mut payout = self.checked_payout(value, price)?;
+        let remainder = price.0 - payout.values().map(|amount| amount.0).sum::<u128>();
+        if remainder > 0 {
+            let seller_amount = payout.entry(seller_id.clone()).or_insert(U128(0));
+            seller_amount.0 += remainder;
+        }
+        let royalty_cap = match self.royalty_cap(price) {
+            Some(royalty_cap) if total_royalties(&payout, seller_id) > royalty_cap => royalty_cap,
+            _ => return Some(payout),
+        };
+
+        let mut royalties: Vec<(AccountId, u128)> = payout
+            .into_iter()
+            .filter(|(receiver_id, _)| receiver_id != seller_id)
+            .map(|(receiver_id, amount)| (receiver_id, amount.0))
+            .collect();
+        let total = royalties.iter().map(|(_, amount)| amount).sum::<u128>();
+
+        match self.over_cap_policy {
+            OverCapPolicy::Clamp | OverCapPolicy::Reject => {
+                for (_, amount) in royalties.iter_mut() {
+                    *amount = mul_div(royalty_cap, *amount, total);
+                }
+            }
+            OverCapPolicy::PaySellerRemainder => {
+                royalties.sort_by_key(|(_, amount)| std::cmp::Reverse(*amount));
+                let mut left = royalty_cap;
+                for (_, amount) in royalties.iter_mut() {
+                    *amount = (*amount).min(left);
+                    left -= *amount;
+                }
+            }
+        }
+
+        let paid = royalties.iter().map(|(_, amount)| amount).sum::<u128>();
+        let mut capped: HashMap<AccountId, U128> = royalties
+            .into_iter()
+            .filter(|(_, amount)| *amount > 0)
+            .map(|(receiver_id, amount)| (receiver_id, U128(amount)))
+            .collect();
+        capped.insert(seller_id.clone(), U128(price.0 - paid));
+        Some(capped)
+    }
+
+    fn royalty_cap(&self, price: U128) -> Option<Balance> {
+        self.royalty_cap_bps
+            .map(|royalty_cap_bps| price.0 * u128::from(royalty_cap_bps) / BASIS_POINTS)
+    }
+}
+
+fn total_royalties(payout: &HashMap<AccountId, U128>, seller_id: &AccountId) -> Balance {
+    payout
+        .iter()
+        .filter(|(receiver_id, _)| *receiver_id != seller_id)
+        .map(|(_, amount)| amount.0)
+        .sum()
+}
+
+/// `a * b / c` rounded down, with the product kept in 256 bits. The result must fit in a u128.
+fn mul_div(a: u128, b: u128, c: u128) -> u128 {
+    const LOW: u128 = u64::MAX as u128;
+    let (a_high, a_low) = (a >> 64, a & LOW);
+    let (b_high, b_low) = (b >> 64, b & LOW);
+    let low_low = a_low * b_low;
+    let high_low = a_high * b_low;
+    let low_high = a_low * b_high;
+    let middle = (low_low >> 64) + (high_low & LOW) + (low_high & LOW);
+    let low = (low_low & LOW) | (middle << 64);
+    let high = a_high * b_high + (high_low >> 64) + (low_high >> 64) + (middle >> 64);
+
+    let mut quotient = 0;
+    let mut remainder: u128 = 0;
+    for bit in (0..256).rev() {
+        let next = if bit >= 128 {
+            (high >> (bit - 128)) & 1
+        } else {
+            (low >> bit) & 1
+        };
+        let carry = remainder >> 127;
+        remainder = (remainder << 1) | next;
+        if carry == 1 || remainder >= c {
+            remainder = remainder.wrapping_sub(c);
+            if bit < 128 {
+                quotient |= 1 << bit;
+            }
+        }
+    }
+    quotient
+}
+
+pub(crate) fn pay_out(payout: HashMap<AccountId, U128>) {
+    for (receiver_id, amount) in payout {
+        Promise::new(receiver_id).transfer(amount.into());
+    }
+}
+
+impl Contract {
+    pub(crate) fn payout_policy(&self, nft_contract_id: &NFTContractId) -> PayoutPolicy {
+        let collection = self.collections.get(nft_contract_id);
+        PayoutPolicy {
+            max_len_payout: collection
+                .as_ref()
+                .and_then(|c| c.max_len_payout)
+                .unwrap_or(DEFAULT_MAX_LEN_PAYOUT),
+            rounding_tolerance: collection
+                .as_ref()
+                .and_then(|c| c.rounding_tolerance)
+                .map(|tolerance| tolerance.0)
+                .unwrap_or(DEFAULT_ROUNDING_TOLERANCE),
+            royalty_cap_bps: collection.as_ref().and_then(|c| c.royalty_cap_bps),
+            over_cap_policy: collection
+                .and_then(|c| c.over_cap_policy)
+                .unwrap_or(OverCapPolicy::Clamp),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use near_sdk::test_utils::VMContextBuilder;
+    use near_sdk::{testing_env, MockedBlockchain};
+
+    const SELLER: &str = "seller.near";
+
+    fn policy(royalty_cap_bps: Option<u32>, over_cap_policy: OverCapPolicy) -> PayoutPolicy {
+        testing_env!(VMContextBuilder::new().build());
+        PayoutPolicy {
+            max_len_payout: DEFAULT_MAX_LEN_PAYOUT,
+            rounding_tolerance: 2,
+            royalty_cap_bps,
+            over_cap_policy,
+        }
+    }
+
+    fn payout_json(payout: &[(&str, Balance)]) -> Vec<u8> {
+        near_sdk::serde_json::to_vec(&Payout {
+            payout: payout
+                .iter()
+                .map(|(receiver_id, amount)| (receiver_id.to_string(), U128(*amount)))
+                .collect(),
+        })
+        .unwrap()
+    }
+
+    fn amounts(payout: HashMap<AccountId, U128>) -> Vec<(AccountId, Balance)> {
+        let mut amounts: Vec<(AccountId, Balance)> = payout
+            .into_iter()
+            .map(|(receiver_id, amount)| (receiver_id, amount.0))
+            .collect();
+        amounts.sort();
+        amounts
+    }
+
+    #[test]
+    fn checked_payout_allows_rounding_tolerance() {
+        let policy = policy(None, OverCapPolicy::Clamp);
+        let price = U128(1_000);
+        assert!(policy
+            .checked_payout(&payout_json(&[(SELLER, 900), ("artist.near", 98)]), price)
+            .is_some());
+        assert!(policy
+            .checked_payout(&payout_json(&[(SELLER, 900), ("artist.near", 97)]), price)
+            .is_none());
+        assert!(policy
+            .checked_payout(&payout_json(&[(SELLER, 900), ("artist.near", 101)]), price)
+            .is_none());
+        assert!(policy.checked_payout(&payout_json(&[]), price).is_none());
+    }
+
+    #[test]
+    fn apply_credits_remainder_to_seller() {
+        let policy = policy(None, OverCapPolicy::Clamp);
+        let payout = policy
+            .apply(
+                &payout_json(&[("artist.near", 100), ("buyer.near", 898)]),
+                U128(1_000),
+                &SELLER.to_string(),
+            )
+            .unwrap();
+        assert_eq!(
+            amounts(payout),
+            vec![
+                ("artist.near".to_string(), 100),
+                ("buyer.near".to_string(), 898),
+                (SELLER.to_string(), 2),
+            ]
+        );
+    }
+
+    #[test]
+    fn exceeds_royalty_cap_ignores_seller() {
+        let uncapped = policy(None, OverCapPolicy::Clamp);
+        let policy = policy(Some(1_000), OverCapPolicy::Clamp);
+        let seller_id = SELLER.to_string();
+        let within: HashMap<AccountId, U128> = vec![
+            (SELLER.to_string(), U128(900)),
+            ("artist.near".to_string(), U128(100)),
+        ]
+        .into_iter()
+        .collect();
+        let over: HashMap<AccountId, U128> = vec![
+            (SELLER.to_string(), U128(899)),
+            ("artist.near".to_string(), U128(101)),
+        ]
+        .into_iter()
+        .collect();
+        assert!(!policy.exceeds_royalty_cap(&within, U128(1_000), &seller_id));
+        assert!(policy.exceeds_royalty_cap(&over, U128(1_000), &seller_id));
+        assert!(!uncapped.exceeds_royalty_cap(&over, U128(1_000), &seller_id));
+    }
+
+    #[test]
+    fn apply_clamps_royalties_proportionally() {
+        let policy = policy(Some(1_000), OverCapPolicy::Clamp);
+        let payout = policy
+            .apply(
+                &payout_json(&[(SELLER, 500), ("artist.near", 300), ("label.near", 200)]),
+                U128(1_000),
+                &SELLER.to_string(),
+            )
+            .unwrap();
+        assert_eq!(
+            amounts(payout),
+            vec![
+                ("artist.near".to_string(), 60),
+                ("label.near".to_string(), 40),
+                (SELLER.to_string(), 900),
+            ]
+        );
+    }
+
+    #[test]
+    fn apply_clamp_keeps_shares_below_one_basis_point() {
+        let policy = policy(Some(1_000), OverCapPolicy::Clamp);
+        let price = 10u128.pow(12);
+        let small = price / 1_000_000;
+        let payout = policy
+            .apply(
+                &payout_json(&[
+                    (SELLER, price / 2 - small),
+                    ("artist.near", price / 2),
+                    ("tiny.near", small),
+                ]),
+                U128(price),
+                &SELLER.to_string(),
+            )
+            .unwrap();
+        let payout = amounts(payout);
+        let royalty_cap = price / 10;
+        assert_eq!(payout[0].0, "artist.near");
+        assert_eq!(payout[1].0, SELLER);
+        assert_eq!(payout[2].0, "tiny.near");
+        assert_eq!(payout[2].1, royalty_cap * small / (price / 2 + small));
+        assert!(payout[2].1 > 0);
+        assert!(payout[0].1 + payout[2].1 <= royalty_cap);
+        assert_eq!(payout.iter().map(|(_, amount)| amount).sum::<u128>(), price);
+    }
+
+    #[test]
+    fn mul_div_does_not_overflow() {
+        let price = 10u128.pow(30);
+        assert_eq!(
+            mul_div(price / 10, price / 100_000, price / 2 + price / 100_000),
+            1_999_960_000_799_984_000_319_993
+        );
+        assert_eq!(mul_div(u128::MAX, 1 << 127, u128::MAX), 1 << 127);
+    }
+
+    #[test]
+    fn apply_pays_seller_remainder_largest_first() {
+        let policy = policy(Some(1_000), OverCapPolicy::PaySellerRemainder);
+        let payout = policy
+            .apply(
+                &payout_json(&[(SELLER, 500), ("artist.near", 300), ("label.near", 200)]),
+                U128(1_000),
+                &SELLER.to_string(),
+            )
+            .unwrap();
+        assert_eq!(
+            amounts(payout),
+            vec![("artist.near".to_string(), 100), (SELLER.to_string(), 900)]
+        );
+    }
+}
@@ -3,7 +3,33 @@ use std::collections::HashMap;
 use crate::*;
 
 pub const GAS_FOR_ROYALTIES: Gas = 115_000_000_000_000;
-const GAS_FOR_NFT_TRANSFER: Gas = 15_000_000_000_000;
+pub const GAS_FOR_NFT_TRANSFER: Gas = 15_000_000_000_000;
+pub const GAS_FOR_NFT_VIEW: Gas = 10_000_000_000_000;
+// `nft_is_approved` only reads the approvals of a token
+const GAS_FOR_NFT_IS_APPROVED: Gas = 5_000_000_000_000;
+// Fees of a call whose result is passed to a callback, 14 Tgas
+pub const GAS_FOR_CALL_FEES: Gas = 15_000_000_000_000;
+// `check_approval` with the fees of its views
+pub const GAS_FOR_CHECK_APPROVAL: Gas =
+    GAS_FOR_NFT_IS_APPROVED + GAS_FOR_NFT_VIEW + 2 * GAS_FOR_CALL_FEES;
+// Kept by `resolve_offer` and `resolve_offer_batch` for reading the checks
+const GAS_FOR_RESOLVE_OFFER: Gas = 5_000_000_000_000;
+// Removal of a bought sale and its uses, 36 Tgas with 100k sales and uses listed
+const GAS_FOR_REMOVE_PURCHASED: Gas = 40_000_000_000_000;
+// Fees of the promises of a purchase, kept back from the gas forwarded to `resolve_purchase`
+const GAS_FOR_PURCHASE_PROMISES: Gas = 20_000_000_000_000;
+// Settling a checked offer besides the gas of its `resolve_purchase`
+const GAS_FOR_SETTLE_OFFER: Gas =
+    GAS_FOR_REMOVE_PURCHASED + GAS_FOR_PURCHASE_PROMISES + GAS_FOR_NFT_TRANSFER;
+// Least `resolve_purchase` pays out with, 5 Tgas for 10 payouts. A single `offer` forwards
+// `GAS_FOR_ROYALTIES`
+const MIN_GAS_FOR_RESOLVE_PURCHASE: Gas = 15_000_000_000_000;
+// Used by `offer` and `offer_batch` after the callback gas is computed
+const GAS_FOR_OFFER: Gas = 10_000_000_000_000;
+// Removal of a stale sale and its uses, 36 Tgas with 100k sales and uses listed
+const GAS_FOR_RESOLVE_REVALIDATE: Gas = 40_000_000_000_000;
+const GAS_FOR_RESOLVE_TRANSFER: Gas = 45_000_000_000_000;
+const GAS_FOR_RESOLVE_NFT_PAYOUT: Gas = 20_000_000_000_000;
 pub const NO_DEPOSIT: Balance = 0;
 #[derive(Serialize, Deserialize)]
 #[serde(crate = "near_sdk::serde")]
@@ -11,6 +37,43 @@ pub struct Payout {
     pub payout: HashMap<AccountId, U128>,
 }
 
+// Only the part of the NEP-171 `JsonToken` the market needs
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct TokenOwner {
+    pub owner_id: AccountId,
+}
+
+/// An offer `resolve_offer_batch` settles after the checks of `offer_batch`.
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct CheckedOffer {
+    pub nft_contract_id: AccountId,
+    pub token_id: TokenId,
+    pub approval_id: u64,
+    pub price: U128,
+    // The royalties were checked with `nft_payout` after `check_listing`
+    pub checks_payout: bool,
+}
+
+impl Sale {
+    pub fn is_reserved(&self) -> bool {
+        !self.reserved_for.is_empty()
+    }
+
+    pub fn can_buy(&self, buyer_id: &AccountId) -> bool {
+        !self.is_reserved() || self.reserved_for.contains(buyer_id)
+    }
+
+    /// Reserved sales are only listed to their owner and reserved buyers.
+    pub fn is_visible_to(&self, viewer_id: Option<&AccountId>) -> bool {
+        match viewer_id {
+            Some(viewer_id) => &self.owner_id == viewer_id || self.can_buy(viewer_id),
+            None => !self.is_reserved(),
+        }
+    }
+}
+
 #[ext_contract(nft_contract)]
 pub trait NFTContract {
     fn nft_transfer_payout(
@@ -22,11 +85,75 @@ pub trait NFTContract {
         balance: U128,
         max_len_payout: u32,
     ) -> Payout;
+    fn nft_transfer(
+        &mut self,
+        receiver_id: AccountId,
+        token_id: TokenId,
+        approval_id: Option<u64>,
+        memo: Option<String>,
+    );
+    fn nft_payout(&self, token_id: TokenId, balance: U128, max_len_payout: u32) -> Payout;
+    fn nft_is_approved(
+        &self,
+        token_id: TokenId,
+        approved_account_id: AccountId,
+        approval_id: Option<u64>,
+    ) -> bool;
+    fn nft_token(&self, token_id: TokenId) -> Option<TokenOwner>;
 }
 
 #[ext_contract(ext_self)]
 pub trait MarketContract {
-    fn resolve_purchase(&mut self, buyer_id: AccountId, price: U128) -> Promise;
+    fn resolve_purchase(&mut self, buyer_id: AccountId, price: U128, sale: Sale) -> U128;
+    fn resolve_transfer(&mut self, buyer_id: AccountId, price: U128, sale: Sale) -> Promise;
+    fn resolve_nft_payout(
+        &mut self,
+        seller_id: AccountId,
+        price: U128,
+        nft_contract_id: AccountId,
+    ) -> U128;
+    fn resolve_offer(
+        &mut self,
+        nft_contract_id: AccountId,
+        token_id: TokenId,
+        approval_id: u64,
+        buyer_id: AccountId,
+        price: U128,
+    );
+    fn resolve_offer_batch(&mut self, buyer_id: AccountId, offers: Vec<CheckedOffer>);
+    fn resolve_revalidate(
+        &mut self,
+        nft_contract_id: AccountId,
+        token_id: TokenId,
+        approval_id: u64,
+    ) -> bool;
+}
+
+/// Asks the NFT contract whether the market approval and the owner of `sale` are still current.
+/// Resolved with `listing_status`.
+pub(crate) fn check_listing(sale: &Sale) -> Promise {
+    check_approval(&sale.nft_contract_id, &sale.token_id, sale.approval_id)
+}
+
+pub(crate) fn check_approval(
+    nft_contract_id: &AccountId,
+    token_id: &TokenId,
+    approval_id: u64,
+) -> Promise {
+    nft_contract::nft_is_approved(
+        token_id.clone(),
+        env::current_account_id(),
+        Some(approval_id),
+        nft_contract_id,
+        NO_DEPOSIT,
+        GAS_FOR_NFT_IS_APPROVED,
+    )
+    .and(nft_contract::nft_token(
+        token_id.clone(),
+        nft_contract_id,
+        NO_DEPOSIT,
+        GAS_FOR_NFT_VIEW,
+    ))
 }
 
 #[near_bindgen]
@@ -46,23 +173,62 @@ impl Contract {
     #[payable]
     pub fn update_price(&mut self, nft_contract_id: AccountId, token_id: TokenId, price: U128) {
         assert_one_yocto();
-        let contract_and_token_id = format!(
-            "{}{}{}",
-            nft_contract_id.clone(),
-            DELIMETER,
-            token_id.clone()
-        );
-        let mut sale = self
-            .sales
-            .get(&contract_and_token_id)
-            .expect("Not found sale");
-        assert_eq!(
-            env::predecessor_account_id(),
-            sale.owner_id,
-            "Must be sale owner"
+        self.internal_update_price(
+            &env::predecessor_account_id(),
+            nft_contract_id,
+            token_id,
+            price,
         );
-        sale.sale_conditions = price;
-        self.sales.insert(&contract_and_token_id, &sale);
+    }
+
+    #[payable]
+    pub fn remove_sale_batch(&mut self, items: Vec<(AccountId, TokenId)>) {
+        assert_one_yocto();
+        let owner_id = env::predecessor_account_id();
+        for (nft_contract_id, token_id) in items {
+            let sale = self.internal_remove_sale(nft_contract_id, token_id);
+            assert_eq!(owner_id, sale.owner_id, "Must be owner id");
+        }
+    }
+
+    #[payable]
+    pub fn update_price_batch(&mut self, items: Vec<(AccountId, TokenId, U128)>) {
+        assert_one_yocto();
+        let owner_id = env::predecessor_account_id();
+        for (nft_contract_id, token_id, price) in items {
+            self.internal_update_price(&owner_id, nft_contract_id, token_id, price);
+        }
+    }
+
+    /**
+    Removes up to `limit` sales of the caller in key order, starting after `from` when given,
+    call again while sales are left. Returns how many sales of the caller are left.
+     */
+    #[payable]
+    pub fn remove_all_sales_by_owner(
+        &mut self,
+        from: Option<ContractAndTokenId>,
+        limit: U64,
+    ) -> U64 {
+        assert_one_yocto();
+        let owner_id = env::predecessor_account_id();
+        let contract_token_ids: Vec<ContractAndTokenId> = self
+            .sales_by_owner
+            .get(&owner_id)
+            .map(|sales_by_owner| {
+                index_keys_after(&sales_by_owner, from)
+                    .take(limit.0 as usize)
+                    .collect()
+            })
+            .unwrap_or_default();
+        for contract_and_token_id in contract_token_ids {
+            let sale = self
+                .sales
+                .get(&contract_and_token_id)
+                .expect("Not found sale");
+            self.internal_remove_sale(sale.nft_contract_id, sale.token_id);
+        }
+        U64(self.get_supply_by_owner_id(owner_id).0 as u64)
     }
 
     #[payable]
@@ -80,7 +246,7 @@ impl Contract {
             .get(&contract_and_token_id)
             .expect("Not found sale");
         let buyer_id = env::predecessor_account_id();
-        assert_ne!(buyer_id, sale.owner_id, "Can not bid on your own sale");
+        self.assert_sale_purchasable(&contract_and_token_id, &sale, &buyer_id);
 
         let price = sale.sale_conditions.0;
         assert!(
@@ -89,7 +255,242 @@ impl Contract {
             price
         );
 
-        self.process_purchase(nft_contract_id, token_id, U128(deposit), buyer_id);
+        let gas_for_checks = self.gas_for_checks(&nft_contract_id);
+        let gas_needed = env::used_gas()
+            + gas_for_checks
+            + GAS_FOR_RESOLVE_OFFER
+            + GAS_FOR_SETTLE_OFFER
+            + GAS_FOR_ROYALTIES
+            + GAS_FOR_OFFER;
+        assert!(
+            env::prepaid_gas() >= gas_needed,
+            "Require at least {} prepaid gas",
+            gas_needed
+        );
+        let gas_for_resolve_offer =
+            env::prepaid_gas() - env::used_gas() - gas_for_checks - GAS_FOR_OFFER;
+        let price = U128(deposit);
+        self.offer_checks(&sale, price)
+            .then(ext_self::resolve_offer(
+                sale.nft_contract_id,
+                sale.token_id,
+                sale.approval_id,
+                buyer_id,
+                price,
+                &env::current_account_id(),
+                NO_DEPOSIT,
+                gas_for_resolve_offer,
+            ));
+    }
+
+    /**
+    Buys several sales at once: items are (nft_contract_id, token_id, max_price). Each sale goes
+    through the same checks as `offer`, then `resolve_offer_batch` settles every item in its own
+    promise chain from one shared budget. The buyer is refunded for any item that fails, along
+    with whatever is attached above the total price.
+     */
+    #[payable]
+    pub fn offer_batch(&mut self, items: Vec<(AccountId, TokenId, U128)>) {
+        assert!(!items.is_empty(), "Require at least one item");
+        let deposit = env::attached_deposit();
+        let buyer_id = env::predecessor_account_id();
+
+        let mut offers = vec![];
+        let mut checks: Option<Promise> = None;
+        let mut total_price: Balance = 0;
+        let mut gas_for_checks: Gas = 0;
+        for (nft_contract_id, token_id, max_price) in items.iter() {
+            let contract_and_token_id = format!("{}{}{}", nft_contract_id, DELIMETER, token_id);
+            let sale = self
+                .sales
+                .get(&contract_and_token_id)
+                .expect("Not found sale");
+            self.assert_sale_purchasable(&contract_and_token_id, &sale, &buyer_id);
+            assert!(
+                sale.sale_conditions.0 <= max_price.0,
+                "Price of {} is above max_price: {}",
+                contract_and_token_id,
+                sale.sale_conditions.0
+            );
+            total_price += sale.sale_conditions.0;
+            gas_for_checks += self.gas_for_checks(nft_contract_id);
+            offers.push((sale, self.payout_policy(nft_contract_id).rejects_over_cap()));
+        }
+        assert!(
+            deposit >= total_price && deposit > 0,
+            "Attached deposit must be greater than or equal total price: {}",
+            total_price
+        );
+        let gas_for_items =
+            offers.len() as Gas * (GAS_FOR_SETTLE_OFFER + MIN_GAS_FOR_RESOLVE_PURCHASE);
+        let gas_needed = env::used_gas()
+            + gas_for_checks
+            + GAS_FOR_RESOLVE_OFFER
+            + gas_for_items
+            + GAS_FOR_OFFER;
+        assert!(
+            env::prepaid_gas() >= gas_needed,
+            "Require at least {} prepaid gas",
+            gas_needed
+        );
+
+        let offers: Vec<CheckedOffer> = offers
+            .into_iter()
+            .map(|(sale, checks_payout)| {
+                let price = sale.sale_conditions;
+                let check = self.offer_checks(&sale, price);
+                checks = Some(match checks.take() {
+                    Some(checks) => checks.and(check),
+                    None => check,
+                });
+                CheckedOffer {
+                    nft_contract_id: sale.nft_contract_id,
+                    token_id: sale.token_id,
+                    approval_id: sale.approval_id,
+                    price,
+                    checks_payout,
+                }
+            })
+            .collect();
+        let gas_for_resolve = env::prepaid_gas()
+            .saturating_sub(env::used_gas() + GAS_FOR_OFFER)
+            .saturating_sub(gas_for_checks);
+        checks
+            .expect("Require at least one item")
+            .then(ext_self::resolve_offer_batch(
+                buyer_id.clone(),
+                offers,
+                &env::current_account_id(),
+                NO_DEPOSIT,
+                gas_for_resolve,
+            ));
+
+        if deposit > total_price {
+            Promise::new(buyer_id).transfer(deposit - total_price);
+        }
+    }
+
+    #[private]
+    pub fn resolve_offer(
+        &mut self,
+        nft_contract_id: AccountId,
+        token_id: TokenId,
+        approval_id: u64,
+        buyer_id: AccountId,
+        price: U128,
+    ) {
+        // Third result is the `nft_payout` checked for collections with `OverCapPolicy::Reject`
+        let offer = CheckedOffer {
+            nft_contract_id,
+            token_id,
+            approval_id,
+            price,
+            checks_payout: env::promise_results_count() == 3,
+        };
+        let sale = if let Some(sale) = self.internal_check_offer(&offer, &buyer_id, 0) {
+            sale
+        } else {
+            Promise::new(buyer_id).transfer(price.0);
+            return;
+        };
+        if env::prepaid_gas().saturating_sub(env::used_gas())
+            < GAS_FOR_SETTLE_OFFER + MIN_GAS_FOR_RESOLVE_PURCHASE
+        {
+            env::log("Not enough gas to settle the purchase, refunding buyer".as_bytes());
+            Promise::new(buyer_id).transfer(price.0);
+            return;
+        }
+        self.internal_settle_offer(sale, price, buyer_id, None);
+    }
+
+    /**
+    Settles the offers of `offer_batch` in order, reading the results of their checks one after
+    the other. Gas above what every offer needs is shared evenly by their purchases, offers that
+    fail their checks are refunded together.
+     */
+    #[private]
+    pub fn resolve_offer_batch(&mut self, buyer_id: AccountId, offers: Vec<CheckedOffer>) {
+        let gas_for_offers =
+            offers.len() as Gas * (GAS_FOR_SETTLE_OFFER + MIN_GAS_FOR_RESOLVE_PURCHASE);
+        let extra_gas_per_offer = env::prepaid_gas()
+            .saturating_sub(env::used_gas() + GAS_FOR_RESOLVE_OFFER + gas_for_offers)
+            / offers.len().max(1) as Gas;
+
+        let mut refund: Balance = 0;
+        let mut first_result = 0;
+        for (index, offer) in offers.iter().enumerate() {
+            let result = first_result;
+            first_result += if offer.checks_payout { 3 } else { 2 };
+            let sale = if let Some(sale) = self.internal_check_offer(offer, &buyer_id, result) {
+                sale
+            } else {
+                refund += offer.price.0;
+                continue;
+            };
+            let offers_left = (offers.len() - index) as Gas;
+            if env::prepaid_gas().saturating_sub(env::used_gas())
+                < offers_left * (GAS_FOR_SETTLE_OFFER + MIN_GAS_FOR_RESOLVE_PURCHASE)
+            {
+                env::log("Not enough gas to settle the purchase, refunding buyer".as_bytes());
+                refund += offer.price.0;
+                continue;
+            }
+            self.internal_settle_offer(
+                sale,
+                offer.price,
+                buyer_id.clone(),
+                Some(MIN_GAS_FOR_RESOLVE_PURCHASE + extra_gas_per_offer),
+            );
+        }
+
+        if refund > 0 {
+            Promise::new(buyer_id).transfer(refund);
+        }
+    }
+
+    /**
+    Removes the sale of a token whose owner or market approval changed on the NFT contract.
+    Anyone can call it.
+     */
+    pub fn revalidate_listing(&mut self, nft_contract_id: AccountId, token_id: TokenId) -> Promise {
+        let contract_and_token_id = format!("{}{}{}", nft_contract_id, DELIMETER, token_id);
+        let sale = self
+            .sales
+            .get(&contract_and_token_id)
+            .expect("Not found sale");
+        check_listing(&sale).then(ext_self::resolve_revalidate(
+            nft_contract_id,
+            token_id,
+            sale.approval_id,
+            &env::current_account_id(),
+            NO_DEPOSIT,
+            GAS_FOR_RESOLVE_REVALIDATE,
+        ))
+    }
+
+    #[private]
+    pub fn resolve_revalidate(
+        &mut self,
+        nft_contract_id: AccountId,
+        token_id: TokenId,
+        approval_id: u64,
+    ) -> bool {
+        let contract_and_token_id = format!("{}{}{}", nft_contract_id, DELIMETER, token_id);
+        let sale = match self.sales.get(&contract_and_token_id) {
+            Some(sale) if sale.approval_id == approval_id => sale,
+            _ => return false,
+        };
+        match listing_status(0, &sale.owner_id) {
+            ListingStatus::Valid => true,
+            ListingStatus::Stale => {
+                self.internal_remove_listing(nft_contract_id, token_id);
+                false
+            }
+            ListingStatus::Unknown => {
+                env::log("Could not check the listing, keeping it".as_bytes());
+                false
+            }
+        }
     }
 
     #[private]
@@ -100,29 +501,492 @@ impl Contract {
         price: U128,
         buyer_id: AccountId,
     ) -> Promise {
-        let sale = self.internal_remove_sale(nft_contract_id.clone(), token_id.clone());
-        self.internal_remove_uses(nft_contract_id.clone(), token_id.clone());
-        nft_contract::nft_transfer_payout(
-            buyer_id.clone(),
+        self.internal_process_purchase(
+            nft_contract_id,
             token_id,
-            sale.approval_id,
-            "Payout from market contract".to_string(),
             price,
-            10,
-            &nft_contract_id,
-            1,
-            GAS_FOR_NFT_TRANSFER,
-        )
-        .then(ext_self::resolve_purchase(
             buyer_id,
+            GAS_FOR_ROYALTIES,
+        )
+    }
+
+    /**
+    An NFT contract whose transfer standard is not pinned yet gets `nft_transfer_payout` and is
+    recorded as `PayoutOnTransfer` once that succeeds. A failed call only refunds the buyer: it
+    can not tell a missing method from a rejected transfer, so plain NEP-171 contracts settle
+    through `nft_transfer` only after a curator pins them with `set_transfer_standard`.
+     */
+    #[private]
+    pub fn resolve_purchase(&mut self, buyer_id: AccountId, price: U128, sale: Sale) -> U128 {
+        if !is_promise_success() {
+            self.internal_release_drop_purchase(&sale, &buyer_id);
+        } else if self.transfer_standards.get(&sale.nft_contract_id).is_none() {
+            self.transfer_standards
+                .insert(&sale.nft_contract_id, &TransferStandard::PayoutOnTransfer);
+        }
+        let nft_contract_id = sale.nft_contract_id.clone();
+        self.internal_payout(buyer_id, price, &nft_contract_id, sale.owner_id)
+    }
+
+    /// Gas of the checks `offer_checks` runs on an NFT contract, with the fees of their results.
+    pub(crate) fn gas_for_checks(&self, nft_contract_id: &NFTContractId) -> Gas {
+        if self.payout_policy(nft_contract_id).rejects_over_cap() {
+            GAS_FOR_CHECK_APPROVAL + GAS_FOR_NFT_VIEW + GAS_FOR_CALL_FEES
+        } else {
+            GAS_FOR_CHECK_APPROVAL
+        }
+    }
+
+    /**
+    Checks the listing with `check_listing`, followed by the royalties for collections with
+    `OverCapPolicy::Reject`. Resolved with `internal_check_offer`.
+     */
+    pub(crate) fn offer_checks(&self, sale: &Sale, price: U128) -> Promise {
+        let payout_policy = self.payout_policy(&sale.nft_contract_id);
+        let checks = check_listing(sale);
+        if !payout_policy.rejects_over_cap() {
+            return checks;
+        }
+        checks.and(nft_contract::nft_payout(
+            sale.token_id.clone(),
             price,
-            &env::current_account_id(),
+            payout_policy.max_len_payout,
+            &sale.nft_contract_id,
             NO_DEPOSIT,
-            GAS_FOR_ROYALTIES,
+            GAS_FOR_NFT_VIEW,
         ))
     }
 
-    pub fn resolve_purchase(&mut self, buyer_id: AccountId, price: U128) -> U128 {
-        self.internal_payout(buyer_id, price)
+    /**
+    Reads the results of `offer_checks` for `offer` from `first_result` on. Returns the sale
+    to settle, or `None` when the buyer has to be refunded, stale listings are removed.
+     */
+    fn internal_check_offer(
+        &mut self,
+        offer: &CheckedOffer,
+        buyer_id: &AccountId,
+        first_result: u64,
+    ) -> Option<Sale> {
+        let contract_and_token_id =
+            format!("{}{}{}", offer.nft_contract_id, DELIMETER, offer.token_id);
+        let sale = match self.sales.get(&contract_and_token_id) {
+            Some(sale) if sale.approval_id == offer.approval_id => sale,
+            _ => {
+                env::log("Sale is no longer available, refunding buyer".as_bytes());
+                return None;
+            }
+        };
+
+        match listing_status(first_result, &sale.owner_id) {
+            ListingStatus::Valid => {}
+            ListingStatus::Stale => {
+                env::log("Approval or owner changed, removing stale listing".as_bytes());
+                self.internal_remove_listing(offer.nft_contract_id.clone(), offer.token_id.clone());
+                return None;
+            }
+            ListingStatus::Unknown => {
+                env::log("Could not check the listing, refunding buyer".as_bytes());
+                return None;
+            }
+        }
+
+        if offer.price.0 < sale.sale_conditions.0 {
+            env::log("Sale price changed, refunding buyer".as_bytes());
+            return None;
+        }
+
+        if let Some(reason) = self.drop_restriction(&sale, buyer_id) {
+            env::log(format!("{}, refunding buyer", reason).as_bytes());
+            return None;
+        }
+
+        if offer.checks_payout {
+            let payout_policy = self.payout_policy(&offer.nft_contract_id);
+            let within_cap = promise_result_at(first_result + 2)
+                .and_then(|value| payout_policy.checked_payout(&value, offer.price))
+                .map(|payout| {
+                    !payout_policy.exceeds_royalty_cap(&payout, offer.price, &sale.owner_id)
+                })
+                .unwrap_or(false);
+            if !within_cap {
+                env::log("Royalties exceed the collection cap, refunding buyer".as_bytes());
+                return None;
+            }
+        }
+        Some(sale)
+    }
+
+    /**
+    Removes the records of a checked sale and transfers it. `resolve_purchase` gets
+    `gas_for_resolve_purchase`, or everything the removal left when not given.
+     */
+    fn internal_settle_offer(
+        &mut self,
+        sale: Sale,
+        price: U128,
+        buyer_id: AccountId,
+        gas_for_resolve_purchase: Option<Gas>,
+    ) -> Promise {
+        let sale = self.internal_remove_purchased(&sale.nft_contract_id, &sale.token_id, &buyer_id);
+        let gas_for_resolve_purchase = gas_for_resolve_purchase.unwrap_or_else(|| {
+            env::prepaid_gas()
+                .saturating_sub(env::used_gas() + GAS_FOR_PURCHASE_PROMISES + GAS_FOR_NFT_TRANSFER)
+        });
+        self.internal_transfer_purchased(sale, price, buyer_id, gas_for_resolve_purchase)
+    }
+
+    pub(crate) fn internal_process_purchase(
+        &mut self,
+        nft_contract_id: AccountId,
+        token_id: TokenId,
+        price: U128,
+        buyer_id: AccountId,
+        gas_for_resolve_purchase: Gas,
+    ) -> Promise {
+        let sale = self.internal_remove_purchased(&nft_contract_id, &token_id, &buyer_id);
+        self.internal_transfer_purchased(sale, price, buyer_id, gas_for_resolve_purchase)
+    }
+
+    /// Removes the sale and uses of a token being bought, the sale counts towards the drop limit.
+    fn internal_remove_purchased(
+        &mut self,
+        nft_contract_id: &AccountId,
+        token_id: &TokenId,
+        buyer_id: &AccountId,
+    ) -> Sale {
+        let sale = self.internal_remove_sale(nft_contract_id.clone(), token_id.clone());
+        self.internal_record_drop_purchase(&sale, buyer_id);
+        self.internal_remove_uses(nft_contract_id, token_id);
+        sale
+    }
+
+    fn internal_transfer_purchased(
+        &self,
+        sale: Sale,
+        price: U128,
+        buyer_id: AccountId,
+        gas_for_resolve_purchase: Gas,
+    ) -> Promise {
+        let nft_contract_id = sale.nft_contract_id.clone();
+        let token_id = sale.token_id.clone();
+        let max_len_payout = self.payout_policy(&nft_contract_id).max_len_payout;
+        match self.transfer_standards.get(&nft_contract_id) {
+            Some(TransferStandard::PayoutOnTransfer) | None => nft_contract::nft_transfer_payout(
+                buyer_id.clone(),
+                token_id,
+                sale.approval_id,
+                "Payout from market contract".to_string(),
+                price,
+                max_len_payout,
+                &nft_contract_id,
+                1,
+                GAS_FOR_NFT_TRANSFER,
+            )
+            .then(ext_self::resolve_purchase(
+                buyer_id,
+                price,
+                sale,
+                &env::current_account_id(),
+                NO_DEPOSIT,
+                gas_for_resolve_purchase,
+            )),
+            Some(_) => transfer_without_payout(buyer_id, price, sale),
+        }
+    }
+
+    #[private]
+    pub fn resolve_transfer(
+        &mut self,
+        buyer_id: AccountId,
+        price: U128,
+        sale: Sale,
+    ) -> PromiseOrValue<U128> {
+        if !is_promise_success() {
+            self.internal_release_drop_purchase(&sale, &buyer_id);
+            Promise::new(buyer_id).transfer(price.0);
+            return PromiseOrValue::Value(price);
+        }
+
+        if self.transfer_standards.get(&sale.nft_contract_id) == Some(TransferStandard::NoPayout) {
+            Promise::new(sale.owner_id).transfer(price.0);
+            return PromiseOrValue::Value(price);
+        }
+
+        let max_len_payout = self.payout_policy(&sale.nft_contract_id).max_len_payout;
+        PromiseOrValue::Promise(
+            nft_contract::nft_payout(
+                sale.token_id,
+                price,
+                max_len_payout,
+                &sale.nft_contract_id,
+                NO_DEPOSIT,
+                GAS_FOR_NFT_VIEW,
+            )
+            .then(ext_self::resolve_nft_payout(
+                sale.owner_id,
+                price,
+                sale.nft_contract_id,
+                &env::current_account_id(),
+                NO_DEPOSIT,
+                GAS_FOR_RESOLVE_NFT_PAYOUT,
+            )),
+        )
+    }
+
+    /**
+    The token already belongs to the buyer here, so without a usable payout the seller
+    is paid in full. The pinned transfer standard is left as is.
+     */
+    #[private]
+    pub fn resolve_nft_payout(
+        &mut self,
+        seller_id: AccountId,
+        price: U128,
+        nft_contract_id: AccountId,
+    ) -> U128 {
+        let payout_policy = self.payout_policy(&nft_contract_id);
+        match promise_result_as_success()
+            .and_then(|value| payout_policy.apply(&value, price, &seller_id))
+        {
+            Some(payout) => pay_out(payout),
+            None => {
+                Promise::new(seller_id).transfer(price.0);
+            }
+        }
+        price
+    }
+}
+
+fn transfer_without_payout(buyer_id: AccountId, price: U128, sale: Sale) -> Promise {
+    nft_contract::nft_transfer(
+        buyer_id.clone(),
+        sale.token_id.clone(),
+        Some(sale.approval_id),
+        Some("Payout from market contract".to_string()),
+        &sale.nft_contract_id,
+        1,
+        GAS_FOR_NFT_TRANSFER,
+    )
+    .then(ext_self::resolve_transfer(
+        buyer_id,
+        price,
+        sale,
+        &env::current_account_id(),
+        NO_DEPOSIT,
+        GAS_FOR_RESOLVE_TRANSFER,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use near_sdk::test_utils::{accounts, get_created_receipts, VMContextBuilder};
+    use near_sdk::{testing_env, MockedBlockchain, RuntimeFeesConfig, VMConfig};
+
+    const MAX_PREPAID_GAS: Gas = 300_000_000_000_000;
+    const PRICE: Balance = 1_000;
+
+    fn call(
+        predecessor_id: usize,
+        deposit: Balance,
+        prepaid_gas: Gas,
+        results: Vec<PromiseResult>,
+    ) {
+        testing_env!(
+            VMContextBuilder::new()
+                .current_account_id(accounts(0))
+                .predecessor_account_id(accounts(predecessor_id))
+                .attached_deposit(deposit)
+                .prepaid_gas(prepaid_gas)
+                .build(),
+            VMConfig::default(),
+            RuntimeFeesConfig::default(),
+            Default::default(),
+            results
+        );
+    }
+
+    fn list(contract: &mut Contract, token_id: &str) {
+        contract.internal_add_sale(&Sale {
+            owner_id: accounts(1).into(),
+            approval_id: 1,
+            nft_contract_id: accounts(2).into(),
+            token_id: token_id.to_string(),
+            sale_conditions: U128(PRICE),
+            reserved_for: vec![],
+            drop_id: None,
+            listed_at: U64(0),
+        });
+        contract.internal_add_uses(&Uses {
+            owner_id: accounts(1).into(),
+            nft_contract_id: accounts(2).into(),
+            token_id: token_id.to_string(),
+            use_conditions: U128(PRICE),
+            listed_at: U64(0),
+        });
+    }
+
+    /// Results of `check_listing` for a listing of `accounts(1)` that is still valid.
+    fn listing_checks(listings: usize) -> Vec<PromiseResult> {
+        (0..listings)
+            .flat_map(|_| {
+                vec![
+                    PromiseResult::Successful(b"true".to_vec()),
+                    PromiseResult::Successful(
+                        format!("{{\"owner_id\":\"{}\"}}", accounts(1)).into_bytes(),
+                    ),
+                ]
+            })
+            .collect()
+    }
+
+    /// Gas attached to the calls of `method_name` the last call created.
+    fn gas_of_calls(method_name: &str) -> Vec<Gas> {
+        get_created_receipts()
+            .iter()
+            .flat_map(|receipt| {
+                near_sdk::serde_json::to_value(receipt).unwrap()["actions"]
+                    .as_array()
+                    .unwrap()
+                    .clone()
+            })
+            .filter(|action| action["FunctionCall"]["method_name"] == method_name)
+            .map(|action| action["FunctionCall"]["gas"].as_u64().unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn offer_fits_prepaid_gas() {
+        call(0, 0, MAX_PREPAID_GAS, vec![]);
+        let mut contract = Contract::new(accounts(0).into());
+        list(&mut contract, "1");
+
+        call(3, PRICE, MAX_PREPAID_GAS, vec![]);
+        contract.offer(accounts(2).into(), "1".to_string());
+        let resolve_offer_gas = gas_of_calls("resolve_offer");
+        assert_eq!(resolve_offer_gas.len(), 1);
+
+        call(0, 0, resolve_offer_gas[0], listing_checks(1));
+        contract.resolve_offer(
+            accounts(2).into(),
+            "1".to_string(),
+            1,
+            accounts(3).into(),
+            U128(PRICE),
+        );
+        assert!(contract
+            .get_sale(accounts(2).into(), "1".to_string())
+            .is_none());
+        assert!(gas_of_calls("resolve_purchase")[0] >= GAS_FOR_ROYALTIES);
+    }
+
+    #[test]
+    fn offer_batch_fits_two_items() {
+        call(0, 0, MAX_PREPAID_GAS, vec![]);
+        let mut contract = Contract::new(accounts(0).into());
+        let token_ids = ["1", "2"];
+        for token_id in token_ids.iter() {
+            list(&mut contract, token_id);
+        }
+
+        call(3, 2 * PRICE, MAX_PREPAID_GAS, vec![]);
+        contract.offer_batch(
+            token_ids
+                .iter()
+                .map(|token_id| (accounts(2).into(), token_id.to_string(), U128(PRICE)))
+                .collect(),
+        );
+        let resolve_gas = gas_of_calls("resolve_offer_batch");
+        assert_eq!(resolve_gas.len(), 1);
+
+        call(0, 0, resolve_gas[0], listing_checks(token_ids.len()));
+        contract.resolve_offer_batch(
+            accounts(3).into(),
+            token_ids
+                .iter()
+                .map(|token_id| CheckedOffer {
+                    nft_contract_id: accounts(2).into(),
+                    token_id: token_id.to_string(),
+                    approval_id: 1,
+                    price: U128(PRICE),
+                    checks_payout: false,
+                })
+                .collect(),
+        );
+        assert_eq!(contract.get_supply_sales().0, 0);
+        assert_eq!(contract.get_supply_uses().0, 0);
+        let resolve_purchase_gas = gas_of_calls("resolve_purchase");
+        assert_eq!(resolve_purchase_gas.len(), 2);
+        assert!(resolve_purchase_gas
+            .iter()
+            .all(|gas| *gas >= MIN_GAS_FOR_RESOLVE_PURCHASE));
+    }
+
+    #[test]
+    fn offer_keeps_listing_when_check_fails() {
+        call(0, 0, MAX_PREPAID_GAS, vec![]);
+        let mut contract = Contract::new(accounts(0).into());
+        list(&mut contract, "1");
+
+        let mut checks = listing_checks(1);
+        checks[0] = PromiseResult::Failed;
+        call(0, 0, MAX_PREPAID_GAS, checks);
+        contract.resolve_offer(
+            accounts(2).into(),
+            "1".to_string(),
+            1,
+            accounts(3).into(),
+            U128(PRICE),
+        );
+        assert!(contract
+            .get_sale(accounts(2).into(), "1".to_string())
+            .is_some());
+        assert!(gas_of_calls("resolve_purchase").is_empty());
+    }
+
+    #[test]
+    fn offer_removes_stale_listing() {
+        call(0, 0, MAX_PREPAID_GAS, vec![]);
+        let mut contract = Contract::new(accounts(0).into());
+        list(&mut contract, "1");
+
+        let mut checks = listing_checks(1);
+        checks[0] = PromiseResult::Successful(b"false".to_vec());
+        call(0, 0, MAX_PREPAID_GAS, checks);
+        contract.resolve_offer(
+            accounts(2).into(),
+            "1".to_string(),
+            1,
+            accounts(3).into(),
+            U128(PRICE),
+        );
+        assert!(contract
+            .get_sale(accounts(2).into(), "1".to_string())
+            .is_none());
+        assert!(gas_of_calls("resolve_purchase").is_empty());
+    }
+
+    #[test]
+    fn remove_all_sales_by_owner_from_key() {
+        call(0, 0, MAX_PREPAID_GAS, vec![]);
+        let mut contract = Contract::new(accounts(0).into());
+        for token_id in ["1", "2", "3", "4", "5"].iter() {
+            list(&mut contract, token_id);
+        }
+
+        call(1, 1, MAX_PREPAID_GAS, vec![]);
+        let from = format!("{}{}{}", accounts(2), DELIMETER, "3");
+        assert_eq!(contract.remove_all_sales_by_owner(Some(from), U64(10)).0, 3);
+        assert!(contract
+            .get_sale(accounts(2).into(), "4".to_string())
+            .is_none());
+        assert!(contract
+            .get_sale(accounts(2).into(), "3".to_string())
+            .is_some());
+
+        assert_eq!(contract.remove_all_sales_by_owner(None, U64(2)).0, 1);
+        assert!(contract
+            .get_sale(accounts(2).into(), "3".to_string())
+            .is_some());
     }
 }
@@ -21,57 +21,93 @@ impl Contract {
             U128(0)
         }
     }
-    pub fn get_sales(&self, from_index: Option<u128>, limit: Option<u64>) -> Vec<Sale> {
-        let start = u128::from(from_index.unwrap_or(0));
+    pub fn get_sale(&self, nft_contract_id: NFTContractId, token_id: TokenId) -> Option<Sale> {
         self.sales
-            .values()
-            .skip(start as usize)
-            .take(limit.unwrap_or(0) as usize)
-            .collect()
+            .get(&format!("{}{}{}", nft_contract_id, DELIMETER, token_id))
     }
+    /// Sales reserved for specific buyers are only returned when `viewer_id` may see them.
+    /// Sales of upcoming drops are listed by `get_sales_by_drop_id` instead.
+    /// Sales come in listing order, pass the page's `next_key` as `from_key` for the next one.
+    pub fn get_sales(
+        &self,
+        from_key: Option<U64>,
+        limit: Option<U64>,
+        viewer_id: Option<AccountId>,
+    ) -> Page<Sale, U64> {
+        let keys = seq_keys(
+            &self.sales_by_seq,
+            self.next_sale_seq,
+            from_key.map(|seq| seq.0),
+            false,
+        );
+        let page = filtered_page(keys, limit, |(_, contract_token_id)| {
+            self.live_sale(contract_token_id.as_ref()?, viewer_id.as_ref())
+        });
+        Page {
+            items: page.items,
+            next_key: page.next_key.map(|(seq, _)| U64(seq)),
+        }
+    }
+    /// Sales come in key order, pass the page's `next_key` as `from_key` for the next one.
     pub fn get_sale_by_owner_id(
         &self,
         account_id: AccountId,
-        from_index: Option<u128>,
-        limit: Option<u64>,
-    ) -> Vec<Sale> {
-        let by_owner_id = self.by_owner_id.get(&account_id);
-        let contract_token_ids = if let Some(by_owner_id) = by_owner_id {
-            by_owner_id
+        from_key: Option<ContractAndTokenId>,
+        limit: Option<U64>,
+        viewer_id: Option<AccountId>,
+    ) -> Page<Sale, ContractAndTokenId> {
+        let sales_by_owner = if let Some(sales_by_owner) = self.sales_by_owner.get(&account_id) {
+            sales_by_owner
         } else {
-            return vec![];
+            return Page {
+                items: vec![],
+                next_key: None,
+            };
         };
-        let start = u128::from(from_index.unwrap_or(0));
-        contract_token_ids
-            .as_vector()
-            .iter()
-            .skip(start as usize)
-            .take(limit.unwrap_or(0) as usize)
-            .map(|contract_token_id| self.sales.get(&contract_token_id).unwrap())
-            .collect()
+        filtered_page(
+            index_keys_after(&sales_by_owner, from_key),
+            limit,
+            |contract_token_id| self.live_sale(contract_token_id, viewer_id.as_ref()),
+        )
     }
+    /// Sales come in token id order, pass the page's `next_key` as `from_key` for the next one.
     pub fn get_sales_by_cotnract_id(
         &self,
         contract_id: NFTContractId,
-        from_index: Option<u128>,
-        limit: Option<u64>,
-    ) -> Vec<Sale> {
-        let tokens_by_contract_id = self.by_contract_id.get(&contract_id);
-        let token_ids = if let Some(tokens_by_contract_id) = tokens_by_contract_id {
-            tokens_by_contract_id
-        } else {
-            return vec![];
-        };
-        let start = u128::from(from_index.unwrap_or(0));
-        token_ids
-            .iter()
-            .skip(start as usize)
-            .take(limit.unwrap_or(0) as usize)
-            .map(|token_id| {
-                self.sales
-                    .get(&format!("{}{}{}", contract_id, DELIMETER, token_id))
-                    .unwrap()
-            })
-            .collect()
+        from_key: Option<TokenId>,
+        limit: Option<U64>,
+        viewer_id: Option<AccountId>,
+    ) -> Page<Sale, TokenId> {
+        let sales_by_contract =
+            if let Some(sales_by_contract) = self.sales_by_contract.get(&contract_id) {
+                sales_by_contract
+            } else {
+                return Page {
+                    items: vec![],
+                    next_key: None,
+                };
+            };
+        filtered_page(
+            index_keys_after(&sales_by_contract, from_key),
+            limit,
+            |token_id| {
+                self.live_sale(
+                    &format!("{}{}{}", contract_id, DELIMETER, token_id),
+                    viewer_id.as_ref(),
+                )
+            },
+        )
+    }
+}
+
+impl Contract {
+    fn live_sale(
+        &self,
+        contract_token_id: &ContractAndTokenId,
+        viewer_id: Option<&AccountId>,
+    ) -> Option<Sale> {
+        self.sales
+            .get(contract_token_id)
+            .filter(|sale| self.is_live_for(sale, viewer_id))
     }
 }
@@ -0,0 +1,131 @@
+use crate::*;
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(crate = "near_sdk::serde", rename_all = "snake_case")]
+pub enum ListingKind {
+    Sale,
+    Use,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(crate = "near_sdk::serde", rename_all = "snake_case")]
+pub enum ListingSort {
+    PriceAsc,
+    PriceDesc,
+    Newest,
+    Oldest,
+}
+
+/// Filter of `search_listings`. Listings are priced in NEAR and never expire, so there is
+/// nothing to filter by payment token or expiry.
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct ListingFilter {
+    // Sales when not set
+    pub kind: Option<ListingKind>,
+    pub nft_contract_ids: Option<Vec<NFTContractId>>,
+    pub owner_id: Option<AccountId>,
+    pub min_price: Option<U128>,
+    pub max_price: Option<U128>,
+    // Newest first when not set
+    pub sort: Option<ListingSort>,
+    // Sees the sales reserved for it, like `viewer_id` of `get_sales`
+    pub viewer_id: Option<AccountId>,
+}
+
+/**
+Position in the order of `search_listings`: the price of a listing when sorting by price,
+otherwise its number in the listing order, and its key.
+ */
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct ListingCursor {
+    pub sort_key: U128,
+    pub contract_and_token_id: ContractAndTokenId,
+}
+
+impl Contract {
+    /// Adds a sale to the price index and, unless it has a place already, to the listing order.
+    pub(crate) fn internal_index_sale(
+        &mut self,
+        contract_and_token_id: &ContractAndTokenId,
+        sale: &Sale,
+    ) {
+        self.sales_by_price.insert(
+            &(sale.sale_conditions.0, contract_and_token_id.clone()),
+            &(),
+        );
+        if self.sale_seqs.get(contract_and_token_id).is_none() {
+            self.sales_by_seq
+                .insert(&self.next_sale_seq, contract_and_token_id);
+            self.sale_seqs
+                .insert(contract_and_token_id, &self.next_sale_seq);
+            self.next_sale_seq += 1;
+        }
+    }
+
+    /// Removes a sale from the price index and, unless `keep_order`, from the listing order.
+    pub(crate) fn internal_unindex_sale(
+        &mut self,
+        contract_and_token_id: &ContractAndTokenId,
+        sale: &Sale,
+        keep_order: bool,
+    ) {
+        self.sales_by_price
+            .remove(&(sale.sale_conditions.0, contract_and_token_id.clone()));
+        if !keep_order {
+            if let Some(seq) = self.sale_seqs.remove(contract_and_token_id) {
+                self.sales_by_seq.remove(&seq);
+            }
+        }
+    }
+
+    pub(crate) fn internal_index_uses(
+        &mut self,
+        contract_and_token_id: &ContractAndTokenId,
+        uses: &Uses,
+    ) {
+        self.uses_by_price
+            .insert(&(uses.use_conditions.0, contract_and_token_id.clone()), &());
+        if self.use_seqs.get(contract_and_token_id).is_none() {
+            self.uses_by_seq
+                .insert(&self.next_use_seq, contract_and_token_id);
+            self.use_seqs
+                .insert(contract_and_token_id, &self.next_use_seq);
+            self.next_use_seq += 1;
+        }
+    }
+
+    pub(crate) fn internal_unindex_uses(
+        &mut self,
+        contract_and_token_id: &ContractAndTokenId,
+        uses: &Uses,
+        keep_order: bool,
+    ) {
+        self.uses_by_price
+            .remove(&(uses.use_conditions.0, contract_and_token_id.clone()));
+        if !keep_order {
+            if let Some(seq) = self.use_seqs.remove(contract_and_token_id) {
+                self.uses_by_seq.remove(&seq);
+            }
+        }
+    }
+}
+
+/**
+Keys of the listing order `by_seq` after `from_seq` up to `next_seq`, ascending or newest
+first. Gaps left by removed listings are skipped, they still count as examined keys of a page.
+ */
+pub(crate) fn seq_keys<'a>(
+    by_seq: &'a LookupMap<u64, ContractAndTokenId>,
+    next_seq: u64,
+    from_seq: Option<u64>,
+    newest_first: bool,
+) -> Box<dyn Iterator<Item = (u64, Option<ContractAndTokenId>)> + 'a> {
+    let seqs: Box<dyn Iterator<Item = u64>> = if newest_first {
+        Box::new((0..from_seq.unwrap_or(next_seq).min(next_seq)).rev())
+    } else {
+        Box::new(from_seq.map(|seq| seq.saturating_add(1)).unwrap_or(0)..next_seq)
+    };
+    Box::new(seqs.map(move |seq| (seq, by_seq.get(&seq))))
+}
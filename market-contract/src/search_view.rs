@@ -0,0 +1,110 @@
+use crate::listing_view::Listing;
+use crate::*;
+
+#[near_bindgen]
+impl Contract {
+    /**
+    Listings matching `filter` in the requested order, pass the page's `next_key` as `from_key`
+    for the next one. Price ranges are read from the price index when sorting by price; other
+    filters are checked on each listing in order. Newest and oldest follow the listing order.
+     */
+    pub fn search_listings(
+        &self,
+        filter: ListingFilter,
+        from_key: Option<ListingCursor>,
+        limit: Option<U64>,
+    ) -> Page<Listing, ListingCursor> {
+        let kind = filter.kind.unwrap_or(ListingKind::Sale);
+        let (by_price, by_seq, next_seq) = match kind {
+            ListingKind::Sale => (&self.sales_by_price, &self.sales_by_seq, self.next_sale_seq),
+            ListingKind::Use => (&self.uses_by_price, &self.uses_by_seq, self.next_use_seq),
+        };
+        let min_price = filter.min_price.map(|price| price.0).unwrap_or(0);
+        let max_price = filter.max_price.map(|price| price.0).unwrap_or(u128::MAX);
+        let from_key = from_key.map(|cursor| (cursor.sort_key.0, cursor.contract_and_token_id));
+        let from_seq = from_key
+            .as_ref()
+            .map(|(seq, _)| (*seq).min(u64::MAX as u128) as u64);
+        let seq_key =
+            |(seq, key): (u64, Option<ContractAndTokenId>)| (seq as u128, key.unwrap_or_default());
+
+        let keys: Box<dyn Iterator<Item = (u128, ContractAndTokenId)>> =
+            match filter.sort.unwrap_or(ListingSort::Newest) {
+                ListingSort::PriceAsc => Box::new(
+                    by_price
+                        .iter_from(from_key.unwrap_or((min_price, String::new())))
+                        .map(|(price_key, _)| price_key)
+                        .take_while(move |(price, _)| *price <= max_price),
+                ),
+                ListingSort::PriceDesc => {
+                    // Keys are below the cursor, or at most `max_price` on the first page
+                    let from_key = from_key
+                        .or_else(|| max_price.checked_add(1).map(|price| (price, String::new())));
+                    let keys: Box<dyn Iterator<Item = ((u128, ContractAndTokenId), ())>> =
+                        match from_key {
+                            Some(from_key) => Box::new(by_price.iter_rev_from(from_key)),
+                            None => Box::new(by_price.iter_rev()),
+                        };
+                    Box::new(
+                        keys.map(|(price_key, _)| price_key)
+                            .take_while(move |(price, _)| *price >= min_price),
+                    )
+                }
+                // Gaps of the listing order come with an empty key that matches no listing
+                ListingSort::Newest => {
+                    Box::new(seq_keys(by_seq, next_seq, from_seq, true).map(seq_key))
+                }
+                ListingSort::Oldest => {
+                    Box::new(seq_keys(by_seq, next_seq, from_seq, false).map(seq_key))
+                }
+            };
+
+        let page = filtered_page(keys, limit, |(_, contract_and_token_id)| {
+            let (owner_id, nft_contract_id, token_id, price) = match kind {
+                ListingKind::Sale => {
+                    let sale = self.sales.get(contract_and_token_id)?;
+                    if !self.is_live_for(&sale, filter.viewer_id.as_ref()) {
+                        return None;
+                    }
+                    (
+                        sale.owner_id,
+                        sale.nft_contract_id,
+                        sale.token_id,
+                        sale.sale_conditions,
+                    )
+                }
+                ListingKind::Use => {
+                    let uses = self.uses.get(contract_and_token_id)?;
+                    (
+                        uses.owner_id,
+                        uses.nft_contract_id,
+                        uses.token_id,
+                        uses.use_conditions,
+                    )
+                }
+            };
+            let owner_matches = match &filter.owner_id {
+                Some(filter_owner_id) => filter_owner_id == &owner_id,
+                None => true,
+            };
+            let contract_matches = match &filter.nft_contract_ids {
+                Some(nft_contract_ids) => nft_contract_ids.contains(&nft_contract_id),
+                None => true,
+            };
+            if price.0 >= min_price && price.0 <= max_price && owner_matches && contract_matches {
+                Some(self.listing_for(nft_contract_id, token_id, filter.viewer_id.as_ref()))
+            } else {
+                None
+            }
+        });
+        Page {
+            items: page.items,
+            next_key: page
+                .next_key
+                .map(|(sort_key, contract_and_token_id)| ListingCursor {
+                    sort_key: U128(sort_key),
+                    contract_and_token_id,
+                }),
+        }
+    }
+}
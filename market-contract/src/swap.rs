@@ -0,0 +1,535 @@
+use crate::*;
+
+const MAX_SWAP_TOKENS: usize = 4;
+// Used by `execute_swap` besides the transfers into escrow
+const GAS_FOR_EXECUTE_SWAP: Gas = 10_000_000_000_000;
+// Moving one token into escrow, with the fees of passing its result to `resolve_swap`
+const GAS_FOR_ESCROW_SWAP_TOKEN: Gas = GAS_FOR_NFT_TRANSFER + GAS_FOR_CALL_FEES;
+// Used by `resolve_swap` besides its transfers
+const GAS_FOR_RESOLVE_SWAP: Gas = 20_000_000_000_000;
+// Handing one token out of escrow, or back to its owner, in `resolve_swap`
+const GAS_FOR_SWAP_TOKEN: Gas = GAS_FOR_NFT_TRANSFER + 5_000_000_000_000;
+// A swap still settling after this long lost its callback and can be cancelled or executed again
+const SWAP_SETTLEMENT_TIMEOUT: u64 = 60 * 60 * 1_000_000_000;
+
+#[derive(BorshSerialize, BorshDeserialize, Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct SwapToken {
+    pub nft_contract_id: NFTContractId,
+    pub token_id: TokenId,
+    // Set once the owner approves the market with msg {"swap_id": ..}
+    pub approval_id: Option<u64>,
+}
+
+#[derive(BorshSerialize, BorshDeserialize, Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct Swap {
+    pub swap_id: u64,
+    pub proposer_id: AccountId,
+    pub counterparty_id: AccountId,
+    // Tokens of the proposer going to the counterparty
+    pub offered: Vec<SwapToken>,
+    // Tokens of the counterparty going to the proposer
+    pub requested: Vec<SwapToken>,
+    // NEAR paid by the proposer to the counterparty on top of the tokens
+    pub sweetener: U128,
+    // Block timestamp `execute_swap` started settling at
+    pub executing_since: Option<U64>,
+}
+
+impl Swap {
+    pub fn is_settling(&self, now: u64) -> bool {
+        matches!(self.executing_since, Some(executing_since) if now < executing_since.0 + SWAP_SETTLEMENT_TIMEOUT)
+    }
+
+    pub fn is_fully_approved(&self) -> bool {
+        self.offered
+            .iter()
+            .chain(self.requested.iter())
+            .all(|token| token.approval_id.is_some())
+    }
+
+    /// Each token with the party it comes from.
+    fn tokens_with_owner(&self) -> Vec<(&SwapToken, &AccountId)> {
+        self.offered
+            .iter()
+            .map(|token| (token, &self.proposer_id))
+            .chain(
+                self.requested
+                    .iter()
+                    .map(|token| (token, &self.counterparty_id)),
+            )
+            .collect()
+    }
+}
+
+#[ext_contract(ext_self)]
+pub trait SwapResolver {
+    fn resolve_swap(&mut self, swap_id: u64) -> bool;
+}
+
+fn swap_tokens(tokens: Vec<(AccountId, TokenId)>) -> Vec<SwapToken> {
+    tokens
+        .into_iter()
+        .map(|(nft_contract_id, token_id)| SwapToken {
+            nft_contract_id,
+            token_id,
+            approval_id: None,
+        })
+        .collect()
+}
+
+#[near_bindgen]
+impl Contract {
+    /**
+    Proposes trading `offered` tokens of the caller for `requested` tokens of `counterparty_id`.
+    The attached deposit pays `STORAGE_PER_SALE` for the proposal, refunded when it closes,
+    and the rest is the sweetener paid to the counterparty.
+    Both sides then approve their tokens with msg {"swap_id": ..} and either side calls `execute_swap`.
+     */
+    #[payable]
+    pub fn propose_swap(
+        &mut self,
+        counterparty_id: AccountId,
+        offered: Vec<(AccountId, TokenId)>,
+        requested: Vec<(AccountId, TokenId)>,
+    ) -> u64 {
+        let deposit = env::attached_deposit();
+        assert!(
+            deposit >= STORAGE_PER_SALE,
+            "Require deposit minimum of {}",
+            STORAGE_PER_SALE
+        );
+        let proposer_id = env::predecessor_account_id();
+        self.assert_not_banned(&proposer_id);
+        self.assert_not_banned(&counterparty_id);
+        assert_ne!(proposer_id, counterparty_id, "Can not swap with yourself");
+        assert!(
+            !offered.is_empty()
+                && !requested.is_empty()
+                && offered.len() + requested.len() <= MAX_SWAP_TOKENS,
+            "Swap must have tokens on both sides and at most {} tokens",
+            MAX_SWAP_TOKENS
+        );
+        for (nft_contract_id, _) in offered.iter().chain(requested.iter()) {
+            self.assert_collection_listable(nft_contract_id);
+        }
+
+        let swap_id = self.next_swap_id;
+        self.next_swap_id += 1;
+        self.swaps.insert(
+            &swap_id,
+            &Swap {
+                swap_id,
+                proposer_id,
+                counterparty_id,
+                offered: swap_tokens(offered),
+                requested: swap_tokens(requested),
+                sweetener: U128(deposit - STORAGE_PER_SALE),
+                executing_since: None,
+            },
+        );
+        swap_id
+    }
+
+    /**
+    Lets either side withdraw from a swap that is not settling, refunding the proposer.
+    A swap stuck settling for longer than `SWAP_SETTLEMENT_TIMEOUT` can be withdrawn too.
+     */
+    #[payable]
+    pub fn cancel_swap(&mut self, swap_id: u64) {
+        assert_one_yocto();
+        let swap = self.swaps.get(&swap_id).expect("Not found swap");
+        let account_id = env::predecessor_account_id();
+        assert!(
+            account_id == swap.proposer_id || account_id == swap.counterparty_id,
+            "Must be swap party"
+        );
+        assert!(
+            !swap.is_settling(env::block_timestamp()),
+            "Swap is settling"
+        );
+        self.swaps.remove(&swap_id);
+        Promise::new(swap.proposer_id).transfer(swap.sweetener.0 + STORAGE_PER_SALE);
+    }
+
+    /**
+    Settles a fully approved swap through market escrow. Every token is first moved to the
+    market with its approval, which also checks the approval and owner are still current, and
+    only handed out once all of them arrived.
+     */
+    pub fn execute_swap(&mut self, swap_id: u64) -> Promise {
+        let mut swap = self.swaps.get(&swap_id).expect("Not found swap");
+        let account_id = env::predecessor_account_id();
+        assert!(
+            account_id == swap.proposer_id || account_id == swap.counterparty_id,
+            "Must be swap party"
+        );
+        assert!(
+            !swap.is_settling(env::block_timestamp()),
+            "Swap is settling"
+        );
+        assert!(
+            swap.is_fully_approved(),
+            "Swap is not approved by both sides"
+        );
+        let tokens_len = (swap.offered.len() + swap.requested.len()) as u64;
+        let gas_for_resolve_swap = GAS_FOR_RESOLVE_SWAP + tokens_len * GAS_FOR_SWAP_TOKEN;
+        let gas_needed = env::used_gas()
+            + GAS_FOR_EXECUTE_SWAP
+            + tokens_len * GAS_FOR_ESCROW_SWAP_TOKEN
+            + gas_for_resolve_swap;
+        assert!(
+            env::prepaid_gas() >= gas_needed,
+            "Require at least {} prepaid gas",
+            gas_needed
+        );
+
+        let mut transfers: Option<Promise> = None;
+        for (token, _) in swap.tokens_with_owner() {
+            let transfer = nft_contract::nft_transfer(
+                env::current_account_id(),
+                token.token_id.clone(),
+                token.approval_id,
+                Some(format!("Swap {} escrowed by market contract", swap_id)),
+                &token.nft_contract_id,
+                1,
+                GAS_FOR_NFT_TRANSFER,
+            );
+            transfers = Some(match transfers {
+                Some(transfers) => transfers.and(transfer),
+                None => transfer,
+            });
+        }
+
+        swap.executing_since = Some(U64(env::block_timestamp()));
+        self.swaps.insert(&swap_id, &swap);
+        transfers
+            .expect("Swap has no tokens")
+            .then(ext_self::resolve_swap(
+                swap_id,
+                &env::current_account_id(),
+                NO_DEPOSIT,
+                gas_for_resolve_swap,
+            ))
+    }
+
+    /**
+    When every token reached the market they are handed to the other side, the sweetener goes
+    to the counterparty and the swap closes. Otherwise the tokens that arrived go back to their
+    owners and the swap stays open with all approvals cleared, as the market used up or lost
+    them, so both sides approve again before the next `execute_swap`. A token whose transfer
+    out of the market fails stays in escrow until `recover_escrowed_token`.
+     */
+    #[private]
+    pub fn resolve_swap(&mut self, swap_id: u64) -> bool {
+        let mut swap = self.swaps.get(&swap_id).expect("Not found swap");
+        let arrived: Vec<bool> = (0..swap.tokens_with_owner().len())
+            .map(|index| promise_result_at(index as u64).is_some())
+            .collect();
+
+        if arrived.iter().all(|arrived| *arrived) {
+            self.swaps.remove(&swap_id);
+            for (token, owner_id) in swap.tokens_with_owner() {
+                let receiver_id = if owner_id == &swap.proposer_id {
+                    swap.counterparty_id.clone()
+                } else {
+                    swap.proposer_id.clone()
+                };
+                nft_contract::nft_transfer(
+                    receiver_id,
+                    token.token_id.clone(),
+                    None,
+                    Some(format!("Swap {} from market contract", swap_id)),
+                    &token.nft_contract_id,
+                    1,
+                    GAS_FOR_NFT_TRANSFER,
+                );
+            }
+            if swap.sweetener.0 > 0 {
+                Promise::new(swap.counterparty_id.clone()).transfer(swap.sweetener.0);
+            }
+            Promise::new(swap.proposer_id).transfer(STORAGE_PER_SALE);
+            return true;
+        }
+
+        let mut failed = vec![];
+        for ((token, owner_id), arrived) in swap.tokens_with_owner().into_iter().zip(arrived) {
+            if arrived {
+                nft_contract::nft_transfer(
+                    owner_id.clone(),
+                    token.token_id.clone(),
+                    None,
+                    Some(format!("Swap {} rolled back by market contract", swap_id)),
+                    &token.nft_contract_id,
+                    1,
+                    GAS_FOR_NFT_TRANSFER,
+                );
+            } else {
+                failed.push(format!(
+                    "{}{}{}",
+                    token.nft_contract_id, DELIMETER, token.token_id
+                ));
+            }
+        }
+        env::log(
+            format!(
+                "Swap {} failed to escrow: {}, approve the tokens again",
+                swap_id,
+                failed.join(", ")
+            )
+            .as_bytes(),
+        );
+        for token in swap.offered.iter_mut().chain(swap.requested.iter_mut()) {
+            token.approval_id = None;
+        }
+        swap.executing_since = None;
+        self.swaps.insert(&swap_id, &swap);
+        false
+    }
+}
+
+impl Contract {
+    pub(crate) fn internal_approve_swap_token(
+        &mut self,
+        swap_id: u64,
+        nft_contract_id: AccountId,
+        token_id: TokenId,
+        owner_id: AccountId,
+        approval_id: u64,
+    ) {
+        let mut swap = self.swaps.get(&swap_id).expect("Not found swap");
+        assert!(
+            !swap.is_settling(env::block_timestamp()),
+            "Swap is settling"
+        );
+        let tokens = if owner_id == swap.proposer_id {
+            &mut swap.offered
+        } else if owner_id == swap.counterparty_id {
+            &mut swap.requested
+        } else {
+            env::panic("Must be swap party".as_bytes())
+        };
+        let token = tokens
+            .iter_mut()
+            .find(|token| token.nft_contract_id == nft_contract_id && token.token_id == token_id)
+            .expect("Token is not part of the swap");
+        token.approval_id = Some(approval_id);
+        self.swaps.insert(&swap_id, &swap);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use near_sdk::test_utils::{accounts, get_created_receipts, VMContextBuilder};
+    use near_sdk::{testing_env, MockedBlockchain, RuntimeFeesConfig, VMConfig};
+
+    const MAX_PREPAID_GAS: Gas = 300_000_000_000_000;
+
+    fn call(
+        predecessor_id: usize,
+        deposit: Balance,
+        prepaid_gas: Gas,
+        results: Vec<PromiseResult>,
+    ) {
+        testing_env!(
+            VMContextBuilder::new()
+                .current_account_id(accounts(0))
+                .predecessor_account_id(accounts(predecessor_id))
+                .attached_deposit(deposit)
+                .prepaid_gas(prepaid_gas)
+                .build(),
+            VMConfig::default(),
+            RuntimeFeesConfig::default(),
+            Default::default(),
+            results
+        );
+    }
+
+    /// Receiver and arguments of every `nft_transfer` the last call created.
+    fn nft_transfers() -> Vec<(String, near_sdk::serde_json::Value)> {
+        get_created_receipts()
+            .iter()
+            .filter_map(|receipt| {
+                let receipt = near_sdk::serde_json::to_value(receipt).ok()?;
+                let action = receipt["actions"]
+                    .as_array()?
+                    .iter()
+                    .find(|action| action["FunctionCall"]["method_name"] == "nft_transfer")?
+                    .clone();
+                Some((
+                    receipt["receiver_id"].as_str()?.to_string(),
+                    near_sdk::serde_json::from_str(action["FunctionCall"]["args"].as_str()?)
+                        .ok()?,
+                ))
+            })
+            .collect()
+    }
+
+    /// Proposes a swap of two tokens of `accounts(1)` for two of `accounts(3)` on
+    /// `accounts(2)` and approves all of them.
+    fn approved_swap(contract: &mut Contract) -> u64 {
+        call(1, STORAGE_PER_SALE + 100, MAX_PREPAID_GAS, vec![]);
+        let swap_id = contract.propose_swap(
+            accounts(3).into(),
+            vec![
+                (accounts(2).into(), "1".to_string()),
+                (accounts(2).into(), "2".to_string()),
+            ],
+            vec![
+                (accounts(2).into(), "3".to_string()),
+                (accounts(2).into(), "4".to_string()),
+            ],
+        );
+        for (token_id, owner_id) in [("1", 1), ("2", 1), ("3", 3), ("4", 3)].iter() {
+            contract.internal_approve_swap_token(
+                swap_id,
+                accounts(2).into(),
+                token_id.to_string(),
+                accounts(*owner_id).into(),
+                1,
+            );
+        }
+        swap_id
+    }
+
+    fn execute(contract: &mut Contract, swap_id: u64) -> Gas {
+        call(1, 0, MAX_PREPAID_GAS, vec![]);
+        contract.execute_swap(swap_id);
+        let escrowed = nft_transfers();
+        assert_eq!(escrowed.len(), MAX_SWAP_TOKENS);
+        assert!(escrowed
+            .iter()
+            .all(|(_, args)| args["receiver_id"] == accounts(0).to_string()));
+        get_created_receipts()
+            .iter()
+            .filter_map(|receipt| {
+                near_sdk::serde_json::to_value(receipt).unwrap()["actions"][0]["FunctionCall"]
+                    .as_object()
+                    .filter(|call| call["method_name"] == "resolve_swap")
+                    .map(|call| call["gas"].as_u64().unwrap())
+            })
+            .next()
+            .expect("Not found resolve_swap")
+    }
+
+    #[test]
+    fn swap_settles_through_escrow() {
+        call(0, 0, MAX_PREPAID_GAS, vec![]);
+        let mut contract = Contract::new(accounts(0).into());
+        let swap_id = approved_swap(&mut contract);
+        let gas_for_resolve = execute(&mut contract, swap_id);
+
+        call(
+            0,
+            0,
+            gas_for_resolve,
+            (0..MAX_SWAP_TOKENS)
+                .map(|_| PromiseResult::Successful(vec![]))
+                .collect(),
+        );
+        assert!(contract.resolve_swap(swap_id));
+        assert!(contract.get_swap(swap_id).is_none());
+        let handed_out: Vec<(String, String)> = nft_transfers()
+            .into_iter()
+            .map(|(_, args)| {
+                (
+                    args["token_id"].as_str().unwrap().to_string(),
+                    args["receiver_id"].as_str().unwrap().to_string(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            handed_out,
+            vec![
+                ("1".to_string(), accounts(3).to_string()),
+                ("2".to_string(), accounts(3).to_string()),
+                ("3".to_string(), accounts(1).to_string()),
+                ("4".to_string(), accounts(1).to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn swap_returns_escrowed_tokens_on_failure() {
+        call(0, 0, MAX_PREPAID_GAS, vec![]);
+        let mut contract = Contract::new(accounts(0).into());
+        let swap_id = approved_swap(&mut contract);
+        let gas_for_resolve = execute(&mut contract, swap_id);
+
+        call(
+            0,
+            0,
+            gas_for_resolve,
+            vec![
+                PromiseResult::Successful(vec![]),
+                PromiseResult::Successful(vec![]),
+                PromiseResult::Successful(vec![]),
+                PromiseResult::Failed,
+            ],
+        );
+        assert!(!contract.resolve_swap(swap_id));
+        let returned: Vec<(String, String)> = nft_transfers()
+            .into_iter()
+            .map(|(_, args)| {
+                (
+                    args["token_id"].as_str().unwrap().to_string(),
+                    args["receiver_id"].as_str().unwrap().to_string(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            returned,
+            vec![
+                ("1".to_string(), accounts(1).to_string()),
+                ("2".to_string(), accounts(1).to_string()),
+                ("3".to_string(), accounts(3).to_string()),
+            ]
+        );
+        let swap = contract.get_swap(swap_id).unwrap();
+        assert!(swap.executing_since.is_none());
+        assert!(swap
+            .offered
+            .iter()
+            .chain(swap.requested.iter())
+            .all(|token| token.approval_id.is_none()));
+    }
+
+    #[test]
+    fn swaps_by_account_page_over_ids() {
+        call(0, 0, MAX_PREPAID_GAS, vec![]);
+        let mut contract = Contract::new(accounts(0).into());
+        for swap_id in 0..600 {
+            if swap_id % 100 == 0 {
+                call(0, 0, MAX_PREPAID_GAS, vec![]);
+            }
+            let counterparty_id = if swap_id % 100 == 0 { 4 } else { 3 };
+            contract.swaps.insert(
+                &swap_id,
+                &Swap {
+                    swap_id,
+                    proposer_id: accounts(1).into(),
+                    counterparty_id: accounts(counterparty_id).into(),
+                    offered: vec![],
+                    requested: vec![],
+                    sweetener: U128(0),
+                    executing_since: None,
+                },
+            );
+        }
+        contract.next_swap_id = 600;
+        contract.swaps.remove(&200);
+
+        call(0, 0, MAX_PREPAID_GAS, vec![]);
+        let page = contract.get_swaps_by_account(accounts(4).into(), None, None);
+        let swap_ids: Vec<u64> = page.items.iter().map(|swap| swap.swap_id).collect();
+        assert_eq!(swap_ids, vec![0, 100, 300, 400]);
+        assert_eq!(page.next_key.unwrap().0, MAX_PAGE_SCAN - 1);
+
+        let page = contract.get_swaps_by_account(accounts(4).into(), page.next_key, None);
+        let swap_ids: Vec<u64> = page.items.iter().map(|swap| swap.swap_id).collect();
+        assert_eq!(swap_ids, vec![500]);
+        assert!(page.next_key.is_none());
+    }
+}
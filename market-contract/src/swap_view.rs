@@ -0,0 +1,23 @@
+use crate::*;
+
+#[near_bindgen]
+impl Contract {
+    pub fn get_swap(&self, swap_id: u64) -> Option<Swap> {
+        self.swaps.get(&swap_id)
+    }
+
+    /// Swaps the account proposed or is asked for, in id order. Pass `next_key` of the previous
+    /// page as `from_key`.
+    pub fn get_swaps_by_account(
+        &self,
+        account_id: AccountId,
+        from_key: Option<U64>,
+        limit: Option<U64>,
+    ) -> Page<Swap, U64> {
+        filtered_page(ids_after(from_key, self.next_swap_id), limit, |swap_id| {
+            self.swaps
+                .get(&swap_id.0)
+                .filter(|swap| swap.proposer_id == account_id || swap.counterparty_id == account_id)
+        })
+    }
+}
@@ -0,0 +1,177 @@
+use crate::*;
+use near_sdk::json_types::Base58CryptoHash;
+
+#[derive(BorshSerialize, BorshDeserialize, Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct TemplateVersion {
+    // Semantic version, MAJOR.MINOR.PATCH
+    pub version: String,
+    // sha256 of the wasm stored with `store_template_code`
+    pub code_hash: Base58CryptoHash,
+    pub init_method: String,
+    // JSON schema of the args `init_method` takes
+    pub init_args_schema: String,
+}
+
+#[derive(BorshSerialize, BorshDeserialize, Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct Template {
+    pub template_id: String,
+    pub description: String,
+    // Ordered by version, the last one is deployed by default
+    pub versions: Vec<TemplateVersion>,
+    pub fee: Option<DeploymentFee>,
+}
+
+impl Template {
+    pub fn latest(&self) -> &TemplateVersion {
+        self.versions.last().expect("Template has no versions")
+    }
+
+    pub fn version(&self, version: &str) -> Option<&TemplateVersion> {
+        self.versions.iter().find(|v| v.version == version)
+    }
+}
+
+pub(crate) fn parse_version(version: &str) -> (u64, u64, u64) {
+    let parts: Vec<u64> = version
+        .split('.')
+        .map(|part| part.parse().ok())
+        .collect::<Option<_>>()
+        .unwrap_or_default();
+    if parts.len() != 3 {
+        env::panic(format!("Invalid semantic version {}", version).as_bytes());
+    }
+    (parts[0], parts[1], parts[2])
+}
+
+#[near_bindgen]
+impl Contract {
+    /**
+    Stores the wasm passed as raw transaction input once and returns its sha256 hash.
+    The attached deposit pays for the storage of the code, the rest is refunded.
+     */
+    #[payable]
+    pub fn store_template_code(&mut self) -> Base58CryptoHash {
+        self.assert_admin();
+        let initial_storage_usage = env::storage_usage();
+        let code = env::input().expect("Require wasm code as input");
+        let mut code_hash = CryptoHash::default();
+        code_hash.copy_from_slice(&env::sha256(&code));
+        if self.template_code.get(&code_hash).is_none() {
+            self.template_code.insert(&code_hash, &code);
+        }
+        refund_deposit(
+            env::storage_usage() - initial_storage_usage,
+            env::predecessor_account_id(),
+        );
+        code_hash.into()
+    }
+
+    /// Publishes `version` of a template, creating the template on its first version.
+    #[payable]
+    pub fn add_template_version(
+        &mut self,
+        template_id: String,
+        version: String,
+        code_hash: Base58CryptoHash,
+        init_method: String,
+        init_args_schema: String,
+        description: Option<String>,
+    ) {
+        assert_one_yocto();
+        self.assert_admin();
+        assert!(
+            self.template_code.get(&code_hash.into()).is_some(),
+            "Not found template code"
+        );
+        let mut template = self
+            .templates
+            .get(&template_id)
+            .unwrap_or_else(|| Template {
+                template_id: template_id.clone(),
+                description: String::new(),
+                versions: vec![],
+                fee: None,
+            });
+        if let Some(latest) = template.versions.last() {
+            assert!(
+                parse_version(&version) > parse_version(&latest.version),
+                "Version must be greater than {}",
+                latest.version
+            );
+        } else {
+            parse_version(&version);
+        }
+        if let Some(description) = description {
+            template.description = description;
+        }
+        template.versions.push(TemplateVersion {
+            version,
+            code_hash,
+            init_method,
+            init_args_schema,
+        });
+        self.templates.insert(&template_id, &template);
+    }
+
+    /// Removes a template and the code none of the other templates uses.
+    #[payable]
+    pub fn remove_template(&mut self, template_id: String) {
+        assert_one_yocto();
+        self.assert_admin();
+        let template = self
+            .templates
+            .remove(&template_id)
+            .expect("Not found template");
+        for template_version in template.versions {
+            let still_used = self.templates.iter().any(|(_, other)| {
+                other
+                    .versions
+                    .iter()
+                    .any(|other_version| other_version.code_hash == template_version.code_hash)
+            });
+            if !still_used {
+                self.template_code
+                    .remove(&template_version.code_hash.into());
+            }
+        }
+    }
+
+    /**
+    Deploys the latest version of a template to `prefix`.<market account> and calls its
+    `init_method` with `init_args` in the same batch. The deposit and init rules of
+    `create_child_contract` apply, and the template fee is charged on top: NEAR fees are
+    added to the deposit, fungible token fees taken from the caller's fee deposit.
+    Fees are refunded when the deployment fails.
+     */
+    #[payable]
+    pub fn deploy_from_template(
+        &mut self,
+        template_id: String,
+        prefix: String,
+        init_args: String,
+        key_policy: Option<KeyPolicy>,
+        frontend_address: String,
+        contract_name: String,
+    ) -> Promise {
+        let template = self
+            .templates
+            .get(&template_id)
+            .expect("Not found template");
+        let latest = template.latest();
+        let code = self
+            .template_code
+            .get(&latest.code_hash.into())
+            .expect("Not found template code");
+        self.internal_create_child(
+            prefix,
+            code,
+            key_policy,
+            Some((latest.init_method.clone(), init_args)),
+            Some((template_id, latest.version.clone())),
+            frontend_address,
+            contract_name,
+        )
+    }
+}
@@ -0,0 +1,21 @@
+use crate::*;
+
+#[near_bindgen]
+impl Contract {
+    pub fn get_template(&self, template_id: String) -> Option<Template> {
+        self.templates.get(&template_id)
+    }
+
+    /// Templates in id order, pass `next_key` of the previous page as `from_key`.
+    pub fn get_templates(
+        &self,
+        from_key: Option<String>,
+        limit: Option<U64>,
+    ) -> Page<Template, String> {
+        filtered_page(
+            index_keys_after(&self.templates, from_key),
+            limit,
+            |template_id| self.templates.get(template_id),
+        )
+    }
+}
@@ -0,0 +1,209 @@
+use crate::*;
+
+const GAS_FOR_FT_TRANSFER: Gas = 10_000_000_000_000;
+const GAS_FOR_RESOLVE_FT_WITHDRAW: Gas = 10_000_000_000_000;
+const FEE_DEPOSIT_MSG: &str = "deployment_fee";
+
+/// Fee charged for deploying a template, accrued to the treasury once the child is created.
+#[derive(BorshSerialize, BorshDeserialize, Serialize, Deserialize, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub enum DeploymentFee {
+    // Paid in the deposit of `deploy_from_template`
+    Near { amount: U128 },
+    // Paid from the fee deposit the creator made with `ft_transfer_call`
+    FungibleToken { token_id: AccountId, amount: U128 },
+}
+
+#[ext_contract(ext_ft)]
+pub trait FungibleToken {
+    fn ft_transfer(&mut self, receiver_id: AccountId, amount: U128, memo: Option<String>);
+}
+
+#[ext_contract(ext_self)]
+pub trait TreasuryResolver {
+    fn resolve_ft_withdraw(
+        &mut self,
+        account_id: Option<AccountId>,
+        token_id: AccountId,
+        amount: U128,
+    );
+}
+
+/// Both parts can contain the delimiter, so the pair itself is the key.
+pub(crate) fn fee_deposit_key(
+    account_id: &AccountId,
+    token_id: &AccountId,
+) -> (AccountId, AccountId) {
+    (account_id.clone(), token_id.clone())
+}
+
+#[near_bindgen]
+impl Contract {
+    #[payable]
+    pub fn set_template_fee(&mut self, template_id: String, fee: Option<DeploymentFee>) {
+        assert_one_yocto();
+        self.assert_admin();
+        let mut template = self
+            .templates
+            .get(&template_id)
+            .expect("Not found template");
+        template.fee = fee;
+        self.templates.insert(&template_id, &template);
+    }
+
+    /// Credits fee tokens sent with `ft_transfer_call` and msg "deployment_fee" to the sender's fee deposit.
+    pub fn ft_on_transfer(
+        &mut self,
+        sender_id: AccountId,
+        amount: U128,
+        msg: String,
+    ) -> PromiseOrValue<U128> {
+        let token_id = env::predecessor_account_id();
+        let is_fee_token = self.templates.iter().any(|(_, template)| {
+            matches!(template.fee, Some(DeploymentFee::FungibleToken { token_id: fee_token_id, .. }) if fee_token_id == token_id)
+        });
+        if msg != FEE_DEPOSIT_MSG || !is_fee_token {
+            env::log(
+                format!(
+                    "Token {} is not a deployment fee deposit, refunding",
+                    token_id
+                )
+                .as_bytes(),
+            );
+            return PromiseOrValue::Value(amount);
+        }
+        self.internal_credit_fee_deposit(&sender_id, &token_id, amount.0);
+        PromiseOrValue::Value(U128(0))
+    }
+
+    /// Sends the caller's unused fee deposit of `token_id` back.
+    #[payable]
+    pub fn withdraw_fee_deposit(&mut self, token_id: AccountId) -> Promise {
+        assert_one_yocto();
+        let account_id = env::predecessor_account_id();
+        let amount = self
+            .fee_deposits
+            .remove(&fee_deposit_key(&account_id, &token_id))
+            .expect("No fee deposit");
+        ext_ft::ft_transfer(
+            account_id.clone(),
+            U128(amount),
+            None,
+            &token_id,
+            1,
+            GAS_FOR_FT_TRANSFER,
+        )
+        .then(ext_self::resolve_ft_withdraw(
+            Some(account_id),
+            token_id,
+            U128(amount),
+            &env::current_account_id(),
+            NO_DEPOSIT,
+            GAS_FOR_RESOLVE_FT_WITHDRAW,
+        ))
+    }
+
+    /// Sends accrued fees to the owner, NEAR when `token_id` is not set.
+    #[payable]
+    pub fn withdraw_treasury(&mut self, token_id: Option<AccountId>, amount: U128) -> Promise {
+        assert_one_yocto();
+        self.assert_owner();
+        match token_id {
+            None => {
+                assert!(
+                    amount.0 <= self.treasury_balance,
+                    "Treasury only holds {}",
+                    self.treasury_balance
+                );
+                self.treasury_balance -= amount.0;
+                Promise::new(self.owner_id.clone()).transfer(amount.0)
+            }
+            Some(token_id) => {
+                let balance = self.treasury_ft_balances.get(&token_id).unwrap_or(0);
+                assert!(amount.0 <= balance, "Treasury only holds {}", balance);
+                self.treasury_ft_balances
+                    .insert(&token_id, &(balance - amount.0));
+                ext_ft::ft_transfer(
+                    self.owner_id.clone(),
+                    amount,
+                    None,
+                    &token_id,
+                    1,
+                    GAS_FOR_FT_TRANSFER,
+                )
+                .then(ext_self::resolve_ft_withdraw(
+                    None,
+                    token_id,
+                    amount,
+                    &env::current_account_id(),
+                    NO_DEPOSIT,
+                    GAS_FOR_RESOLVE_FT_WITHDRAW,
+                ))
+            }
+        }
+    }
+
+    /// Puts a failed withdrawal back on the fee deposit, or the treasury when `account_id` is not set.
+    #[private]
+    pub fn resolve_ft_withdraw(
+        &mut self,
+        account_id: Option<AccountId>,
+        token_id: AccountId,
+        amount: U128,
+    ) {
+        if is_promise_success() {
+            return;
+        }
+        match account_id {
+            Some(account_id) => self.internal_credit_fee_deposit(&account_id, &token_id, amount.0),
+            None => self.internal_accrue_fee(&DeploymentFee::FungibleToken { token_id, amount }),
+        }
+    }
+}
+
+impl Contract {
+    pub(crate) fn internal_credit_fee_deposit(
+        &mut self,
+        account_id: &AccountId,
+        token_id: &AccountId,
+        amount: Balance,
+    ) {
+        let key = fee_deposit_key(account_id, token_id);
+        let balance = self.fee_deposits.get(&key).unwrap_or(0);
+        self.fee_deposits.insert(&key, &(balance + amount));
+    }
+
+    /// Takes a fungible token fee out of the creator's fee deposit.
+    pub(crate) fn internal_charge_fee_deposit(
+        &mut self,
+        account_id: &AccountId,
+        token_id: &AccountId,
+        amount: Balance,
+    ) {
+        let key = fee_deposit_key(account_id, token_id);
+        let balance = self.fee_deposits.get(&key).unwrap_or(0);
+        assert!(
+            balance >= amount,
+            "Require fee deposit of {} {}, have {}",
+            amount,
+            token_id,
+            balance
+        );
+        if balance == amount {
+            self.fee_deposits.remove(&key);
+        } else {
+            self.fee_deposits.insert(&key, &(balance - amount));
+        }
+    }
+
+    pub(crate) fn internal_accrue_fee(&mut self, fee: &DeploymentFee) {
+        match fee {
+            DeploymentFee::Near { amount } => self.treasury_balance += amount.0,
+            DeploymentFee::FungibleToken { token_id, amount } => {
+                let balance = self.treasury_ft_balances.get(token_id).unwrap_or(0);
+                self.treasury_ft_balances
+                    .insert(token_id, &(balance + amount.0));
+            }
+        }
+    }
+}
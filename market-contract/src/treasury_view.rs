@@ -0,0 +1,19 @@
+use crate::*;
+
+#[near_bindgen]
+impl Contract {
+    pub fn get_treasury_balance(&self, token_id: Option<AccountId>) -> U128 {
+        match token_id {
+            None => U128(self.treasury_balance),
+            Some(token_id) => U128(self.treasury_ft_balances.get(&token_id).unwrap_or(0)),
+        }
+    }
+
+    pub fn get_fee_deposit(&self, account_id: AccountId, token_id: AccountId) -> U128 {
+        U128(
+            self.fee_deposits
+                .get(&fee_deposit_key(&account_id, &token_id))
+                .unwrap_or(0),
+        )
+    }
+}
@@ -16,7 +16,13 @@ pub trait NFTContract {
 
 #[ext_contract(ext_self)]
 pub trait MarketContract {
-    fn resolve_use(&mut self, user_id: AccountId, price: U128) -> Promise;
+    fn resolve_use(
+        &mut self,
+        user_id: AccountId,
+        price: U128,
+        nft_contract_id: AccountId,
+        owner_id: AccountId,
+    ) -> Promise;
 }
 
 #[near_bindgen]
@@ -38,6 +44,7 @@ impl Contract {
             .expect("Not found uses");
 
         let user_id = env::predecessor_account_id();
+        self.assert_not_banned(&user_id);
         assert_ne!(user_id, uses.owner_id, "Can not use your own contract");
         let price = uses.use_conditions.0;
         assert!(
@@ -56,12 +63,18 @@ impl Contract {
         price: U128,
         user_id: AccountId,
     ) {
+        let contract_and_token_id = format!("{}{}{}", nft_contract_id, DELIMETER, token_id);
+        let uses = self
+            .uses
+            .get(&contract_and_token_id)
+            .expect("Not found uses");
+        let max_len_payout = self.payout_policy(&nft_contract_id).max_len_payout;
         nft_contract::nft_use_payout(
             user_id.clone(),
             token_id,
             "Payout for use nft from market_contract".to_string(),
             price,
-            10,
+            max_len_payout,
             &nft_contract_id,
             1,
             GAS_FOR_NFT_USES,
@@ -69,46 +82,67 @@ impl Contract {
         .then(ext_self::resolve_use(
             user_id,
             price,
+            nft_contract_id,
+            uses.owner_id,
             &env::current_account_id(),
             NO_DEPOSIT,
             GAS_FOR_ROYALTIES,
         ));
     }
 
-    pub fn resolve_use(&mut self, user_id: AccountId, price: U128) -> U128 {
-        self.internal_payout(user_id, price)
+    #[private]
+    pub fn resolve_use(
+        &mut self,
+        user_id: AccountId,
+        price: U128,
+        nft_contract_id: AccountId,
+        owner_id: AccountId,
+    ) -> U128 {
+        self.internal_payout(user_id, price, &nft_contract_id, owner_id)
     }
 
     #[payable]
     pub fn update_use_price(&mut self, nft_contract_id: AccountId, token_id: TokenId, price: U128) {
         assert_one_yocto();
-        let contract_and_token_id = format!(
-            "{}{}{}",
-            nft_contract_id.clone(),
-            DELIMETER,
-            token_id.clone()
-        );
-        let mut uses = self
-            .uses
-            .get(&contract_and_token_id)
-            .expect("Not found uses");
-        assert_eq!(
-            env::predecessor_account_id(),
-            uses.owner_id,
-            "Must be sale owner"
+        self.internal_update_use_price(
+            &env::predecessor_account_id(),
+            nft_contract_id,
+            token_id,
+            price,
         );
-        uses.use_conditions = price;
-        self.uses.insert(&contract_and_token_id, &uses);
+    }
+
+    #[payable]
+    pub fn update_use_price_batch(&mut self, items: Vec<(AccountId, TokenId, U128)>) {
+        assert_one_yocto();
+        let owner_id = env::predecessor_account_id();
+        for (nft_contract_id, token_id, price) in items {
+            self.internal_update_use_price(&owner_id, nft_contract_id, token_id, price);
+        }
     }
 
     #[payable]
     pub fn remove_uses(&mut self, nft_contract_id: AccountId, token_id: TokenId) {
         assert_one_yocto();
-        let uses = self.internal_remove_uses(nft_contract_id, token_id);
+        let uses = self
+            .internal_remove_uses(&nft_contract_id, &token_id)
+            .expect("Not found uses");
         assert_eq!(
             env::predecessor_account_id(),
             uses.owner_id,
             "Must be owner id"
         );
     }
+
+    #[payable]
+    pub fn remove_uses_batch(&mut self, items: Vec<(AccountId, TokenId)>) {
+        assert_one_yocto();
+        let owner_id = env::predecessor_account_id();
+        for (nft_contract_id, token_id) in items {
+            let uses = self
+                .internal_remove_uses(&nft_contract_id, &token_id)
+                .expect("Not found uses");
+            assert_eq!(owner_id, uses.owner_id, "Must be owner id");
+        }
+    }
 }
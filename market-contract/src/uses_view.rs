@@ -6,12 +6,25 @@ impl Contract {
         U128(self.uses.len() as u128)
     }
 
-    pub fn get_uses(&self, from_index: Option<u128>, limit: Option<u64>) -> Vec<Uses> {
-        let start = u128::from(from_index.unwrap_or(0));
+    pub fn get_use(&self, nft_contract_id: NFTContractId, token_id: TokenId) -> Option<Uses> {
         self.uses
-            .values()
-            .skip(start as usize)
-            .take(limit.unwrap_or(0) as usize)
-            .collect()
+            .get(&format!("{}{}{}", nft_contract_id, DELIMETER, token_id))
+    }
+
+    /// Uses come in listing order, pass the page's `next_key` as `from_key` for the next one.
+    pub fn get_uses(&self, from_key: Option<U64>, limit: Option<U64>) -> Page<Uses, U64> {
+        let keys = seq_keys(
+            &self.uses_by_seq,
+            self.next_use_seq,
+            from_key.map(|seq| seq.0),
+            false,
+        );
+        let page = filtered_page(keys, limit, |(_, contract_token_id)| {
+            self.uses.get(contract_token_id.as_ref()?)
+        });
+        Page {
+            items: page.items,
+            next_key: page.next_key.map(|(seq, _)| U64(seq)),
+        }
     }
 }
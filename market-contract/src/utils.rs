@@ -8,8 +8,57 @@ pub(crate) fn assert_one_yocto() {
     );
 }
 
+/// Charges `storage_used` bytes from the attached deposit and refunds the rest.
+pub(crate) fn refund_deposit(storage_used: u64, account_id: AccountId) {
+    let required_cost = STORAGE_PRICE_PER_BYTE * Balance::from(storage_used);
+    let attached_deposit = env::attached_deposit();
+    assert!(
+        required_cost <= attached_deposit,
+        "Require deposit minimum of {}",
+        required_cost
+    );
+    let refund = attached_deposit - required_cost;
+    if refund > 1 {
+        Promise::new(account_id).transfer(refund);
+    }
+}
+
 pub(crate) fn hash_account_id(account_id: &AccountId) -> CryptoHash {
     let mut hash = CryptoHash::default();
     hash.copy_from_slice(&env::sha256(account_id.as_bytes()));
     hash
 }
+
+/// What the results of `check_listing` tell about a listing.
+#[derive(PartialEq)]
+pub(crate) enum ListingStatus {
+    Valid,
+    // The NFT contract answered that the approval or the owner changed
+    Stale,
+    // A check failed or returned something else, so the listing may still be valid
+    Unknown,
+}
+
+/// Reads the two results of `check_listing` starting at `first_result`: the market approval
+/// must still be valid and the token must still belong to `owner_id`.
+pub(crate) fn listing_status(first_result: u64, owner_id: &AccountId) -> ListingStatus {
+    if env::promise_results_count() < first_result + 2 {
+        return ListingStatus::Unknown;
+    }
+    let is_approved = promise_result_at(first_result)
+        .and_then(|value| near_sdk::serde_json::from_slice::<bool>(&value).ok());
+    let token_owner = promise_result_at(first_result + 1)
+        .and_then(|value| near_sdk::serde_json::from_slice::<Option<TokenOwner>>(&value).ok());
+    match (is_approved, token_owner) {
+        (Some(true), Some(Some(token))) if &token.owner_id == owner_id => ListingStatus::Valid,
+        (Some(_), Some(_)) => ListingStatus::Stale,
+        _ => ListingStatus::Unknown,
+    }
+}
+
+pub(crate) fn promise_result_at(result_index: u64) -> Option<Vec<u8>> {
+    match env::promise_result(result_index) {
+        PromiseResult::Successful(value) => Some(value),
+        _ => None,
+    }
+}